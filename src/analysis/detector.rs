@@ -1,16 +1,32 @@
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, VecDeque};
 use alloy::primitives::U256;
-use crate::config::Config;
+use crate::analysis::sanctions::SanctionsLists;
+use crate::analysis::scoreboard::BuilderScoreboard;
+use crate::analysis::validation::TxValidator;
+use crate::config::{Config, ThresholdSource};
 use crate::network::state::MempoolState;
 use crate::db::repo::Repository;
-use crate::types::{CensorshipEvent, TrackedTx, MempoolSnapshot};
+use crate::types::{current_timestamp, SLOT_TIME_SECS, CensorshipEvent, CensorshipRate, PendingTx, TrackedTx, MempoolSnapshot, MinedBlock};
+
+// What a recent block left unused and who produced it
+struct BlockRoom {
+    gas_left: u128,
+    builder: String,
+    proposer_index: Option<u64>,
+}
 
 pub struct CensorshipDetector {
     mempool_state: Arc<MempoolState>,
     db: Arc<Repository>,
     config: Config,
-    block_first_seen: Arc<tokio::sync::RwLock<HashMap<String, u64>>>,
+    validator: TxValidator,
+    scoreboard: BuilderScoreboard,
+    sanctions: Arc<SanctionsLists>,
+    // (block number, sorted included tips) of the last `inclusion_window_blocks` blocks
+    recent_tips: tokio::sync::RwLock<VecDeque<(u64, Vec<U256>)>>,
+    // Gas each recent block left unused, to tell full blocks apart from skipped txs
+    block_room: tokio::sync::RwLock<BTreeMap<u64, BlockRoom>>,
 }
 
 impl CensorshipDetector {
@@ -18,48 +34,163 @@ impl CensorshipDetector {
         mempool_state: Arc<MempoolState>,
         db: Arc<Repository>,
         config: Config,
+        sanctions: Arc<SanctionsLists>,
     ) -> Self {
         Self {
+            validator: TxValidator::new(mempool_state.clone(), db.clone(), config.clone()),
+            scoreboard: BuilderScoreboard::new(mempool_state.clone(), db.clone()),
+            sanctions,
             mempool_state,
             db,
             config,
-            block_first_seen: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            recent_tips: tokio::sync::RwLock::new(VecDeque::new()),
+            block_room: tokio::sync::RwLock::new(BTreeMap::new()),
         }
     }
 
-    pub async fn scan_mempool(&self, current_block: u64) -> Vec<CensorshipEvent> {
-        let snapshot = self.mempool_state.calculate_snapshot().await;
+    pub async fn scan_mempool(&self, block: &MinedBlock) -> Vec<CensorshipEvent> {
+        let current_block = block.number;
+        // Score the builder against txs flagged in earlier blocks before flagging new ones,
+        // leaving out those that stopped being includable
+        let flagged = self.scoreboard.flagged_txs().await;
+        self.validator.recheck_flagged(&flagged, current_block).await;
+        self.scoreboard.record_block(block).await;
+
+        // Candidates compete for the next block, so price tips at its predicted base fee
+        let snapshot = self.mempool_state
+            .calculate_snapshot(block.next_base_fee(), self.config.fee_percentile_threshold)
+            .await;
         let pending_txs = self.mempool_state.get_pending_txs().await;
+        let pending_txs = self.validator.check_fee_caps(pending_txs, block).await;
 
-        let mut events = Vec::new();
+        {
+            // Keep roughly as many blocks as a tx stays tracked
+            let history = (self.config.mempool_max_age_secs.max(0) as u64 / SLOT_TIME_SECS).max(1);
+            let mut block_room = self.block_room.write().await;
+            block_room.insert(block.number, BlockRoom {
+                gas_left: block.gas_limit.saturating_sub(block.gas_used),
+                builder: block.builder(),
+                proposer_index: block.proposer_index,
+            });
+            *block_room = block_room.split_off(&block.number.saturating_sub(history));
+        }
+
+        if self.config.threshold_source == ThresholdSource::Inclusion {
+            let mut recent = self.recent_tips.write().await;
+            recent.push_back((block.number, block.included_tips.clone()));
+            while recent.len() > self.config.inclusion_window_blocks {
+                recent.pop_front();
+            }
+            if recent.iter().all(|(_, tips)| tips.is_empty()) {
+                eprintln!("⚠️  No tipped inclusions in the last {} blocks, no inclusion threshold at block #{}",
+                    recent.len(),
+                    block.number
+                );
+            }
+        }
+
+        let mut candidates = Vec::new();
 
         for tracked_tx in pending_txs {
-            if let Some(event) = self.analyze_transaction(&tracked_tx, current_block, &snapshot).await {
-                events.push(event);
+            if let Some(event) = self.analyze_transaction(&tracked_tx, block, &snapshot).await {
+                candidates.push((tracked_tx, event));
+            }
+        }
+
+        let mut events = self.validator.check_on_chain(candidates, block).await;
+
+        if let Err(e) = self.db.insert_snapshot(&snapshot, current_block).await {
+            eprintln!("Failed to store mempool snapshot: {:?}", e);
+        }
+
+        for event in &mut events {
+            if let Some(simulation) = &event.simulation {
+                event.sim_sanctions = self.sanctions.matches_addresses(&simulation.touched);
             }
+            if let Err(e) = self.db.insert_censorship_event(event).await {
+                eprintln!("Failed to store censorship event {}: {:?}", event.tx_hash, e);
+            }
+            self.mempool_state.mark_censored(&event.tx_hash).await;
+            self.scoreboard.flag(&event.tx_hash).await;
         }
 
         events
     }
 
+    // Forget per-block history above `common_ancestor` after a reorg
+    pub async fn rollback(&self, common_ancestor: u64) {
+        self.block_room.write().await.split_off(&(common_ancestor + 1));
+        self.recent_tips.write().await.retain(|(number, _)| *number <= common_ancestor);
+    }
+
+    // Print how often set-code (EIP-7702) txs get flagged compared to other types
+    pub async fn report_by_tx_type(&self) {
+        match self.db.censorship_rates_by_tx_type().await {
+            Ok(rates) => print_rates(&rates),
+            Err(e) => eprintln!("Failed to compute censorship rates: {:?}", e),
+        }
+    }
+
+    // Print how often txs touching sanctioned addresses get flagged compared to the rest
+    pub async fn report_by_sanctions(&self) {
+        if self.config.sanctions_files.is_empty() {
+            return;
+        }
+
+        match self.db.censorship_rates_by_sanctions().await {
+            Ok(rates) => {
+                print_rates(&rates);
+                println!("[REPORT] sanctions matched on sender, recipient, access list and delegations only, contracts reached through internal calls are not compared");
+            }
+            Err(e) => eprintln!("Failed to compute sanctioned censorship rates: {:?}", e),
+        }
+    }
+
+    // Print which builders keep leaving out flagged txs they had room for
+    pub async fn report_by_builder(&self) {
+        self.scoreboard.report().await;
+    }
+
     async fn analyze_transaction(
         &self,
         tracked_tx: &TrackedTx,
-        current_block: u64,
+        block: &MinedBlock,
         snapshot: &MempoolSnapshot,
     ) -> Option<CensorshipEvent> {
+        let current_block = block.number;
         let current_time = current_timestamp();
-        let time_in_mempool = current_time - tracked_tx.tx.first_seen;
+        // Don't count time we couldn't see the mempool as time spent waiting
+        let blind_secs = self.mempool_state
+            .mempool_blind_secs(tracked_tx.tx.first_seen, current_time, self.config.nodes.len())
+            .await;
+        let time_in_mempool = current_time - tracked_tx.tx.first_seen - blind_secs;
 
-        let first_seen_block = {
-            let mut map = self.block_first_seen.write().await;
-            *map.entry(tracked_tx.tx.hash.clone()).or_insert(current_block)
-        };
+        let first_seen_block = tracked_tx.first_seen_block;
 
-        let blocks_waited = current_block.saturating_sub(first_seen_block);
+        // Only blocks that had room for it count, a full block is no sign of censorship
+        let (mut skipped_by, mut skipped_by_proposers): (Vec<String>, Vec<Option<u64>>) = self
+            .producers_with_room(first_seen_block, current_block, tracked_tx.tx.gas_limit)
+            .await
+            .into_iter()
+            .unzip();
+        let blocks_waited = skipped_by.len() as u64;
+        skipped_by.sort();
+        skipped_by.dedup();
+        skipped_by_proposers.sort();
+        skipped_by_proposers.dedup();
+        let skipped_by_proposers: Vec<u64> = skipped_by_proposers.into_iter().flatten().collect();
 
-        let tx_priority_fee = tracked_tx.tx.max_priority_fee;
-        let threshold_fee = snapshot.fee_percentiles.p25;
+        // A tx only a few nodes saw may simply not have propagated to builders
+        let seen_by_nodes = tracked_tx.sightings.len();
+        if seen_by_nodes < self.config.min_seen_by_nodes {
+            return None;
+        }
+
+        let tx_priority_fee = tracked_tx.tx.effective_tip(snapshot.base_fee);
+        let threshold_fee = match self.config.threshold_source {
+            ThresholdSource::Mempool => snapshot.threshold_fee,
+            ThresholdSource::Inclusion => self.inclusion_threshold(first_seen_block).await?,
+        };
 
         if threshold_fee == U256::ZERO {
             return None;
@@ -84,10 +215,14 @@ impl CensorshipDetector {
             return None;
         }
 
+        if tracked_tx.tx.blob_count > 0 && !self.blob_tx_includable(&tracked_tx.tx, block) {
+            return None;
+        }
+
         let time_score = (blocks_waited as f64 / 10.0).min(1.0);
         let confidence_score = (fee_ratio * time_score * 0.5).min(1.0);
 
-        let fee_percentile = self.calculate_percentile(tx_priority_fee, snapshot);
+        let fee_percentile = self.mempool_state.fee_rank(snapshot.base_fee, tx_priority_fee).await;
 
         Some(CensorshipEvent {
             tx_hash: tracked_tx.tx.hash.clone(),
@@ -99,29 +234,66 @@ impl CensorshipDetector {
             blocks_pending: blocks_waited,
             seconds_pending: time_in_mempool,
             confidence_score,
+            seen_by_nodes,
             detected_at_block: current_block,
             detected_at: current_time,
+            simulation: None,
+            skipped_by,
+            skipped_by_proposers,
+            sim_sanctions: Vec::new(),
         })
     }
 
-    fn calculate_percentile(&self, fee: U256, snapshot: &MempoolSnapshot) -> f64 {
-        if fee >= snapshot.fee_percentiles.p90 {
-            0.90
-        } else if fee >= snapshot.fee_percentiles.p75 {
-            0.75
-        } else if fee >= snapshot.fee_percentiles.p50 {
-            0.50
-        } else if fee >= snapshot.fee_percentiles.p25 {
-            0.25
-        } else {
-            0.10
+    // Builder and proposer of each block in (after, up_to] that left at least `gas_limit` unused
+    async fn producers_with_room(&self, after: u64, up_to: u64, gas_limit: u64) -> Vec<(String, Option<u64>)> {
+        if after >= up_to {
+            return Vec::new();
         }
+
+        let block_room = self.block_room.read().await;
+        block_room
+            .range(after + 1..=up_to)
+            .filter(|(_, room)| room.gas_left >= gas_limit as u128)
+            .map(|(_, room)| (room.builder.clone(), room.proposer_index))
+            .collect()
+    }
+
+    // Low percentile of the tips builders accepted in the blocks mined since the tx was first seen,
+    // within the recent window. None if no such block has been recorded yet.
+    async fn inclusion_threshold(&self, first_seen_block: u64) -> Option<U256> {
+        let recent = self.recent_tips.read().await;
+
+        let mut tips: Vec<U256> = recent.iter()
+            .filter(|(number, _)| *number > first_seen_block)
+            .flat_map(|(_, tips)| tips.iter().copied())
+            .collect();
+        if tips.is_empty() {
+            return None;
+        }
+        tips.sort();
+
+        let index = ((tips.len() as f64 * self.config.inclusion_tip_percentile) as usize).min(tips.len() - 1);
+        Some(tips[index])
+    }
+
+    // Blob txs also compete in the blob fee market: they only count if they cover the next
+    // blob base fee and the block just mined had room left for their blobs
+    fn blob_tx_includable(&self, tx: &PendingTx, block: &MinedBlock) -> bool {
+        let params = &self.config.blob_params;
+        let next_blob_base_fee = params.base_fee(params.next_excess_blob_gas(block));
+
+        tx.max_fee_per_blob_gas >= next_blob_base_fee
+            && tx.blob_count as u64 <= params.remaining_blobs(block)
     }
 }
 
-fn current_timestamp() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64
+fn print_rates(rates: &[CensorshipRate]) {
+    for rate in rates {
+        let pct = if rate.total > 0 {
+            rate.censored as f64 * 100.0 / rate.total as f64
+        } else {
+            0.0
+        };
+        println!("[REPORT] {} txs: {}/{} flagged ({:.2}%)", rate.tx_class, rate.censored, rate.total, pct);
+    }
 }
@@ -1 +1,5 @@
 pub mod detector;
+pub mod validation;
+pub mod simulation;
+pub mod scoreboard;
+pub mod sanctions;
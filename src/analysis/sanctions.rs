@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use std::path::Path;
+use alloy::primitives::Address;
+use eyre::{Result, eyre};
+use crate::types::PendingTx;
+
+// Address lists (OFAC SDN crypto addresses, custom lists) loaded from local files
+pub struct SanctionsLists {
+    // (list name, addresses)
+    lists: Vec<(String, HashSet<Address>)>,
+}
+
+impl SanctionsLists {
+    // Each file is either a JSON array of addresses or one address per line, `#` starts a comment.
+    // A list is named after its file.
+    pub fn from_files(paths: &[String]) -> Result<Self> {
+        let mut lists = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| eyre!("Could not read sanctions list {}: {}", path, e))?;
+            let addresses = parse_list(&contents).map_err(|e| eyre!("Invalid sanctions list {}: {}", path, e))?;
+
+            let name = Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone());
+            println!("🚫 Loaded {} addresses from sanctions list {}", addresses.len(), name);
+            lists.push((name, addresses));
+        }
+
+        Ok(Self { lists })
+    }
+
+    // Names of the lists containing the sender, recipient or any contract the tx declares it touches.
+    // Contracts only reached through internal calls are matched on the event, from its simulation.
+    pub fn matches(&self, tx: &PendingTx) -> Vec<String> {
+        let addresses: Vec<Address> = std::iter::once(tx.from)
+            .chain(tx.to)
+            .chain(tx.touched.iter().copied())
+            .chain(tx.delegations.iter().copied())
+            .collect();
+
+        self.matches_addresses(&addresses)
+    }
+
+    // Names of the lists containing any of `addresses`
+    pub fn matches_addresses(&self, addresses: &[Address]) -> Vec<String> {
+        self.lists.iter()
+            .filter(|(_, list)| addresses.iter().any(|address| list.contains(address)))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+fn parse_list(contents: &str) -> Result<HashSet<Address>> {
+    if contents.trim_start().starts_with('[') {
+        let entries: Vec<String> = serde_json::from_str(contents)?;
+        return entries.iter().map(|entry| parse_address(entry)).collect();
+    }
+
+    contents.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(parse_address)
+        .collect()
+}
+
+// Checksums aren't verified, lists mix checksummed and lowercase addresses
+fn parse_address(entry: &str) -> Result<Address> {
+    entry.parse().map_err(|_| eyre!("not an address: {}", entry))
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use crate::network::state::MempoolState;
+use crate::db::repo::Repository;
+use crate::types::{ExclusionReason, MinedBlock, TrackedTx, TxStatus};
+
+// Tracks, block by block, whether the builder had a flagged tx it could have included and
+// whether it left one out. Counts are stored with the block so the scoreboard survives restarts
+// and reorgs (orphaned blocks are deleted with their counts).
+pub struct BuilderScoreboard {
+    mempool_state: Arc<MempoolState>,
+    db: Arc<Repository>,
+    // Txs flagged as potentially censored that may still end up in a block
+    flagged: RwLock<HashSet<String>>,
+}
+
+impl BuilderScoreboard {
+    pub fn new(mempool_state: Arc<MempoolState>, db: Arc<Repository>) -> Self {
+        Self {
+            mempool_state,
+            db,
+            flagged: RwLock::new(HashSet::new()),
+        }
+    }
+
+    // Count from the next block on
+    pub async fn flag(&self, tx_hash: &str) {
+        self.flagged.write().await.insert(tx_hash.to_string());
+    }
+
+    // Flagged txs still waiting for inclusion, to recheck before the next block is scored
+    pub async fn flagged_txs(&self) -> Vec<TrackedTx> {
+        let hashes: Vec<String> = self.flagged.read().await.iter().cloned().collect();
+        self.mempool_state.get_txs(&hashes).await
+            .into_iter()
+            .filter(|tracked_tx| tracked_tx.status == TxStatus::PotentiallyCensored)
+            .collect()
+    }
+
+    // Score `block` against the txs flagged before it. Must run after its inclusions were marked.
+    pub async fn record_block(&self, block: &MinedBlock) {
+        let mut flagged = self.flagged.write().await;
+        if flagged.is_empty() {
+            return;
+        }
+
+        let hashes: Vec<String> = flagged.iter().cloned().collect();
+        let tracked_txs = self.mempool_state.get_txs(&hashes).await;
+        let gas_left = block.gas_limit.saturating_sub(block.gas_used);
+
+        let mut done: HashSet<String> = flagged.clone();
+        let mut eligible = 0u64;
+        let mut skipped = 0u64;
+        for tracked_tx in tracked_txs {
+            match tracked_tx.status {
+                TxStatus::Included { block_number } if block_number == block.number => {
+                    eligible += 1;
+                }
+                // Its nonce was used by another tx, it can never be included
+                TxStatus::PotentiallyCensored if tracked_tx.exclusion == Some(ExclusionReason::NonceStale) => {}
+                TxStatus::PotentiallyCensored => {
+                    done.remove(&tracked_tx.tx.hash);
+                    // Same bar as detection: it could have paid the base fee and fit in the space left
+                    let fits = tracked_tx.exclusion.is_none()
+                        && tracked_tx.tx.max_fee >= block.base_fee
+                        && tracked_tx.tx.gas_limit as u128 <= gas_left;
+                    if fits {
+                        eligible += 1;
+                        skipped += 1;
+                    }
+                }
+                // Put back by a reorg, it may be flagged again
+                TxStatus::Pending => {
+                    done.remove(&tracked_tx.tx.hash);
+                }
+                _ => {}
+            }
+        }
+
+        // Included, dropped, replaced or no longer tracked: nothing left to score
+        flagged.retain(|hash| !done.contains(hash));
+
+        if eligible == 0 {
+            return;
+        }
+        if let Err(e) = self.db.update_block_flagged(block.number, eligible, skipped).await {
+            eprintln!("Failed to store flagged tx counts of block #{}: {:?}", block.number, e);
+        }
+    }
+
+    // Print each builder's skip rate over the blocks it built while a flagged tx was eligible
+    pub async fn report(&self) {
+        let scores = match self.db.builder_scoreboard().await {
+            Ok(scores) => scores,
+            Err(e) => {
+                eprintln!("Failed to compute builder scoreboard: {:?}", e);
+                return;
+            }
+        };
+
+        for score in scores {
+            let (low, high) = score.confidence_interval();
+            println!("[BUILDERS] {} ({}): skipped flagged txs in {}/{} eligible blocks ({:.1}%, 95% CI {:.1}-{:.1}%) | {} blocks built",
+                score.builder,
+                score.fee_recipient,
+                score.skipped_blocks,
+                score.eligible_blocks,
+                score.rate() * 100.0,
+                low * 100.0,
+                high * 100.0,
+                score.blocks
+            );
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use alloy::{
+    consensus::Transaction as _,
+    eips::BlockId,
+    primitives::{Address, B256, U256},
+    providers::Provider,
+    rpc::types::{BlockTransactionsKind, Transaction},
+    sol_types::decode_revert_reason,
+    transports::TransportError,
+};
+use eyre::{Result, eyre};
+use revm::{
+    db::CacheDB,
+    primitives::{
+        AccountInfo, Authorization, AuthorizationList, BlobExcessGasAndPrice, Bytecode, EVMError,
+        ExecutionResult, SignedAuthorization, SpecId, TxKind,
+    },
+    DatabaseRef, Evm,
+};
+use tokio::runtime::Handle;
+use tokio::sync::OnceCell;
+use crate::config::BlobParams;
+use crate::network::source::NodeProvider;
+use crate::types::{MinedBlock, SimulationResult, SLOT_TIME_SECS};
+
+// EIP-7825 (Fusaka) per-tx gas limit cap
+const MAX_TX_GAS_LIMIT: u64 = 1 << 24;
+
+// Executes pending txs in an embedded EVM on top of the latest mined block.
+// State is pulled lazily over RPC, so only the accounts and slots a tx touches are fetched.
+pub struct Simulator {
+    blob_params: BlobParams,
+    chain_id: OnceCell<u64>,
+}
+
+impl Simulator {
+    pub fn new(blob_params: BlobParams) -> Self {
+        Self { blob_params, chain_id: OnceCell::new() }
+    }
+
+    // Returns None for txs we can't simulate faithfully
+    pub async fn simulate(
+        &self,
+        provider: &NodeProvider,
+        block: &MinedBlock,
+        tx_hash: &str,
+    ) -> Result<Option<SimulationResult>> {
+        let hash: B256 = tx_hash.parse()?;
+        let Some(tx) = provider.get_transaction_by_hash(hash).await? else {
+            // Left the node's pool since we saw it
+            return Ok(None);
+        };
+        let chain_id = *self.chain_id.get_or_try_init(|| provider.get_chain_id()).await?;
+        let db = RpcDb {
+            provider: provider.clone(),
+            block: BlockId::number(block.number),
+            handle: Handle::current(),
+        };
+        if tx.inner.gas_limit() > MAX_TX_GAS_LIMIT {
+            return Ok(Some(SimulationResult {
+                gas_used: 0,
+                includable: false,
+                reverted: false,
+                revert_reason: Some(format!("gas limit above the {} per-tx cap", MAX_TX_GAS_LIMIT)),
+                touched: Vec::new(),
+            }));
+        }
+
+        let block = block.clone();
+        // Priced with the configured parameters, revm only knows the Cancun and Prague ones
+        let excess_blob_gas = self.blob_params.next_excess_blob_gas(&block);
+        let blob_price = BlobExcessGasAndPrice {
+            excess_blob_gas,
+            blob_gasprice: self.blob_params.base_fee(excess_blob_gas).saturating_to(),
+        };
+
+        // The EVM is synchronous and blocks on every state fetch, keep it off the runtime threads
+        tokio::task::spawn_blocking(move || execute(db, chain_id, &block, blob_price, &tx)).await?
+    }
+}
+
+fn execute(
+    db: RpcDb,
+    chain_id: u64,
+    block: &MinedBlock,
+    blob_price: BlobExcessGasAndPrice,
+    tx: &Transaction,
+) -> Result<Option<SimulationResult>> {
+    let mut evm = Evm::builder()
+        .with_db(CacheDB::new(db))
+        // Newest fork revm 19 implements (its OSAKA is an EOF draft, not Fusaka). Fusaka's
+        // includability change, the per-tx gas cap, is checked before executing.
+        .with_spec_id(SpecId::PRAGUE)
+        .modify_cfg_env(|cfg| cfg.chain_id = chain_id)
+        .modify_block_env(|env| {
+            // Pretend it goes into the very next block
+            env.number = U256::from(block.number + 1);
+            env.timestamp = U256::from(block.timestamp + SLOT_TIME_SECS);
+            env.gas_limit = U256::from(block.gas_limit);
+            env.basefee = block.next_base_fee();
+            env.prevrandao = Some(B256::ZERO);
+            env.blob_excess_gas_and_price = Some(blob_price);
+        })
+        .modify_tx_env(|env| {
+            env.caller = tx.from;
+            env.transact_to = tx.inner.to().map(TxKind::Call).unwrap_or(TxKind::Create);
+            env.value = tx.inner.value();
+            env.data = tx.inner.input().clone();
+            env.gas_limit = tx.inner.gas_limit();
+            env.gas_price = U256::from(tx.inner.max_fee_per_gas());
+            env.gas_priority_fee = tx.inner.max_priority_fee_per_gas().map(U256::from);
+            env.nonce = Some(tx.inner.nonce());
+            env.chain_id = tx.inner.chain_id();
+            env.access_list = tx.inner.access_list().map(|list| list.0.clone()).unwrap_or_default();
+            env.blob_hashes = tx.inner.blob_versioned_hashes().map(<[B256]>::to_vec).unwrap_or_default();
+            env.max_fee_per_blob_gas = tx.inner.max_fee_per_blob_gas().map(U256::from);
+            env.authorization_list = tx.inner.authorization_list().map(to_revm_authorizations);
+        })
+        .build();
+
+    let (result, touched) = match evm.transact() {
+        // Every account loaded during execution, including contracts reached through internal calls
+        Ok(result) => (result.result, result.state.into_keys().collect::<Vec<_>>()),
+        Err(EVMError::Transaction(invalid)) => {
+            // Fails validation (nonce, balance, fee cap, intrinsic gas...), no builder could include it
+            return Ok(Some(SimulationResult {
+                gas_used: 0,
+                includable: false,
+                reverted: false,
+                revert_reason: Some(invalid.to_string()),
+                touched: Vec::new(),
+            }));
+        }
+        Err(e) => return Err(eyre!("simulation failed: {}", e)),
+    };
+
+    Ok(Some(match result {
+        ExecutionResult::Success { gas_used, .. } => SimulationResult {
+            gas_used,
+            includable: true,
+            reverted: false,
+            revert_reason: None,
+            touched,
+        },
+        ExecutionResult::Revert { gas_used, output } => SimulationResult {
+            gas_used,
+            includable: true,
+            reverted: true,
+            revert_reason: decode_revert_reason(&output),
+            touched,
+        },
+        ExecutionResult::Halt { reason, gas_used } => SimulationResult {
+            gas_used,
+            includable: true,
+            reverted: true,
+            revert_reason: Some(format!("{:?}", reason)),
+            touched,
+        },
+    }))
+}
+
+// revm pins a newer alloy-eip7702 than alloy, so authorizations are rebuilt field by field
+fn to_revm_authorizations(list: &[alloy::eips::eip7702::SignedAuthorization]) -> AuthorizationList {
+    let signed = list.iter()
+        .map(|auth| {
+            let inner = Authorization {
+                chain_id: U256::from(auth.chain_id),
+                address: auth.address,
+                nonce: auth.nonce,
+            };
+            SignedAuthorization::new_unchecked(inner, auth.y_parity(), auth.r(), auth.s())
+        })
+        .collect::<Vec<_>>();
+    AuthorizationList::Signed(signed)
+}
+
+// Read-only view of the node's state at a block, fetched on demand
+struct RpcDb {
+    provider: NodeProvider,
+    block: BlockId,
+    handle: Handle,
+}
+
+impl DatabaseRef for RpcDb {
+    type Error = TransportError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.handle.block_on(async {
+            let (nonce, balance, code) = tokio::try_join!(
+                self.provider.get_transaction_count(address).block_id(self.block),
+                self.provider.get_balance(address).block_id(self.block),
+                self.provider.get_code_at(address).block_id(self.block),
+            )?;
+            let code = Bytecode::new_raw(code);
+            Ok(Some(AccountInfo::new(balance, nonce, code.hash_slow(), code)))
+        })
+    }
+
+    fn code_by_hash_ref(&self, _code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // Code always comes with the account in basic_ref, so revm never needs to look it up
+        Ok(Bytecode::default())
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.handle.block_on(async {
+            self.provider.get_storage_at(address, index).block_id(self.block).await
+        })
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.handle.block_on(async {
+            let block = self.provider
+                .get_block_by_number(number.into(), BlockTransactionsKind::Hashes)
+                .await?;
+            Ok(block.map(|block| block.header.hash).unwrap_or_default())
+        })
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+use std::cmp::Ordering;
+use alloy::eips::eip4844::DATA_GAS_PER_BLOB;
+use alloy::primitives::{Address, U256};
+use crate::analysis::simulation::Simulator;
+use crate::config::Config;
+use crate::network::chain::ChainReader;
+use crate::network::state::MempoolState;
+use crate::db::repo::Repository;
+use crate::types::{CensorshipEvent, ExclusionReason, MinedBlock, TrackedTx, TxStatus};
+
+// Checks that a pending tx could actually be included before it's analyzed or reported.
+// Anything that fails is tagged with an ExclusionReason instead of being flagged.
+pub struct TxValidator {
+    mempool_state: Arc<MempoolState>,
+    db: Arc<Repository>,
+    config: Config,
+    chain: ChainReader,
+    simulator: Simulator,
+}
+
+impl TxValidator {
+    pub fn new(mempool_state: Arc<MempoolState>, db: Arc<Repository>, config: Config) -> Self {
+        Self {
+            mempool_state,
+            db,
+            chain: ChainReader::new(config.clone()),
+            simulator: Simulator::new(config.blob_params),
+            config,
+        }
+    }
+
+    // Cheap, local check run on every pending tx: a fee cap below the base fee
+    // can't be included in this block no matter what the builder wants.
+    pub async fn check_fee_caps(&self, pending_txs: Vec<TrackedTx>, block: &MinedBlock) -> Vec<TrackedTx> {
+        let mut valid = Vec::with_capacity(pending_txs.len());
+
+        for tracked_tx in pending_txs {
+            if tracked_tx.tx.max_fee < block.base_fee {
+                self.set_exclusion(&tracked_tx.tx.hash, Some(ExclusionReason::FeeCapBelowBaseFee)).await;
+            } else {
+                if tracked_tx.exclusion == Some(ExclusionReason::FeeCapBelowBaseFee) {
+                    self.set_exclusion(&tracked_tx.tx.hash, None).await;
+                }
+                valid.push(tracked_tx);
+            }
+        }
+
+        valid
+    }
+
+    // On-chain checks against the state at `block`, only run on candidates since they cost RPC calls
+    pub async fn check_on_chain(
+        &self,
+        candidates: Vec<(TrackedTx, CensorshipEvent)>,
+        block: &MinedBlock,
+    ) -> Vec<CensorshipEvent> {
+        let candidates = if self.config.validate_nonces {
+            self.check_nonces(candidates, block.number).await
+        } else {
+            candidates
+        };
+
+        let candidates = if self.config.validate_balances {
+            self.check_balances(candidates, block.number).await
+        } else {
+            candidates
+        };
+
+        if self.config.simulate_candidates {
+            self.simulate(candidates, block).await
+        } else {
+            candidates.into_iter().map(|(_, event)| event).collect()
+        }
+    }
+
+    // Execute each candidate on top of `block`, attach the outcome to its event and drop
+    // the ones that no builder could include or that left the pool
+    async fn simulate(
+        &self,
+        candidates: Vec<(TrackedTx, CensorshipEvent)>,
+        block: &MinedBlock,
+    ) -> Vec<CensorshipEvent> {
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        let provider = match self.chain.provider().await {
+            Ok(provider) => provider,
+            Err(e) => {
+                eprintln!("⚠️  Simulation skipped at block #{}: {}", block.number, e);
+                return candidates.into_iter().map(|(_, event)| event).collect();
+            }
+        };
+
+        let mut events = Vec::new();
+        for (tracked_tx, mut event) in candidates {
+            let hash = &tracked_tx.tx.hash;
+            match self.simulator.simulate(&provider, block, hash).await {
+                Ok(Some(sim)) if !sim.includable => {
+                    self.set_exclusion(hash, Some(ExclusionReason::NotIncludable)).await;
+                }
+                Ok(Some(sim)) => {
+                    if tracked_tx.exclusion == Some(ExclusionReason::NotIncludable) {
+                        self.set_exclusion(hash, None).await;
+                    }
+                    event.simulation = Some(sim);
+                    events.push(event);
+                }
+                // The node no longer has it, nothing left for a builder to include
+                Ok(None) => self.set_status(hash, TxStatus::Dropped).await,
+                Err(e) => {
+                    eprintln!("⚠️  Failed to simulate {}: {}", hash, e);
+                    self.chain.reset().await;
+                    events.push(event);
+                }
+            }
+        }
+
+        events
+    }
+
+    // Drop candidates whose nonce doesn't match the sender's on-chain nonce, and give
+    // nonce-gap txs whose gap has since been filled another chance.
+    async fn check_nonces(
+        &self,
+        candidates: Vec<(TrackedTx, CensorshipEvent)>,
+        block: u64,
+    ) -> Vec<(TrackedTx, CensorshipEvent)> {
+        let gapped = self.mempool_state.get_nonce_gap_txs().await;
+        let senders: Vec<Address> = candidates.iter()
+            .map(|(tracked_tx, _)| tracked_tx.tx.from)
+            .chain(gapped.iter().map(|tracked_tx| tracked_tx.tx.from))
+            .collect();
+        if senders.is_empty() {
+            return Vec::new();
+        }
+
+        let nonces = match self.chain.nonces(block, &senders).await {
+            Ok(nonces) => nonces,
+            Err(e) => {
+                // Can't verify this round, keep the candidates rather than silently losing them
+                eprintln!("⚠️  Nonce check failed at block #{}: {}", block, e);
+                return candidates;
+            }
+        };
+
+        for tracked_tx in gapped {
+            if nonces.get(&tracked_tx.tx.from) == Some(&tracked_tx.tx.nonce) {
+                self.set_status(&tracked_tx.tx.hash, TxStatus::Pending).await;
+                self.set_exclusion(&tracked_tx.tx.hash, None).await;
+            }
+        }
+
+        let mut valid = Vec::new();
+        for (tracked_tx, event) in candidates {
+            let Some(&on_chain) = nonces.get(&tracked_tx.tx.from) else {
+                valid.push((tracked_tx, event));
+                continue;
+            };
+
+            let hash = &tracked_tx.tx.hash;
+            match tracked_tx.tx.nonce.cmp(&on_chain) {
+                Ordering::Equal => valid.push((tracked_tx, event)),
+                Ordering::Greater => {
+                    self.set_status(hash, TxStatus::NonceGap).await;
+                    self.set_exclusion(hash, Some(ExclusionReason::NonceGap)).await;
+                }
+                Ordering::Less => {
+                    self.set_status(hash, TxStatus::NonceStale).await;
+                    self.set_exclusion(hash, Some(ExclusionReason::NonceStale)).await;
+                }
+            }
+        }
+
+        valid
+    }
+
+    // Drop candidates whose sender can't pay for gas_limit * max_fee, their blob gas and value
+    async fn check_balances(
+        &self,
+        candidates: Vec<(TrackedTx, CensorshipEvent)>,
+        block: u64,
+    ) -> Vec<(TrackedTx, CensorshipEvent)> {
+        if candidates.is_empty() {
+            return candidates;
+        }
+
+        let senders: Vec<Address> = candidates.iter().map(|(tracked_tx, _)| tracked_tx.tx.from).collect();
+        let balances = match self.chain.balances(block, &senders).await {
+            Ok(balances) => balances,
+            Err(e) => {
+                eprintln!("⚠️  Balance check failed at block #{}: {}", block, e);
+                return candidates;
+            }
+        };
+
+        let mut valid = Vec::new();
+        for (tracked_tx, event) in candidates {
+            let hash = &tracked_tx.tx.hash;
+            let affordable = balances
+                .get(&tracked_tx.tx.from)
+                .is_none_or(|balance| *balance >= max_cost(&tracked_tx));
+
+            if affordable {
+                if tracked_tx.exclusion == Some(ExclusionReason::InsufficientBalance) {
+                    self.set_exclusion(hash, None).await;
+                }
+                valid.push((tracked_tx, event));
+            } else {
+                self.set_exclusion(hash, Some(ExclusionReason::InsufficientBalance)).await;
+            }
+        }
+
+        valid
+    }
+
+    // Re-run the nonce and balance checks on flagged txs against the state after `block`, so the
+    // scoreboard stops counting ones that can no longer be included. Only the exclusion tag changes,
+    // they stay censored.
+    pub async fn recheck_flagged(&self, flagged: &[TrackedTx], block: u64) {
+        if flagged.is_empty() {
+            return;
+        }
+
+        let senders: Vec<Address> = flagged.iter().map(|tracked_tx| tracked_tx.tx.from).collect();
+        let nonces = if self.config.validate_nonces {
+            self.chain.nonces(block, &senders).await.unwrap_or_else(|e| {
+                eprintln!("⚠️  Nonce recheck failed at block #{}: {}", block, e);
+                Default::default()
+            })
+        } else {
+            Default::default()
+        };
+        let balances = if self.config.validate_balances {
+            self.chain.balances(block, &senders).await.unwrap_or_else(|e| {
+                eprintln!("⚠️  Balance recheck failed at block #{}: {}", block, e);
+                Default::default()
+            })
+        } else {
+            Default::default()
+        };
+
+        for tracked_tx in flagged {
+            let sender = &tracked_tx.tx.from;
+            let nonce_reason = nonces.get(sender).and_then(|on_chain| match tracked_tx.tx.nonce.cmp(on_chain) {
+                Ordering::Equal => None,
+                Ordering::Greater => Some(ExclusionReason::NonceGap),
+                Ordering::Less => Some(ExclusionReason::NonceStale),
+            });
+            let reason = nonce_reason.or_else(|| {
+                balances.get(sender)
+                    .filter(|balance| **balance < max_cost(tracked_tx))
+                    .map(|_| ExclusionReason::InsufficientBalance)
+            });
+
+            // Leave tags these checks didn't set alone
+            let rechecked = matches!(
+                tracked_tx.exclusion,
+                None | Some(ExclusionReason::NonceGap | ExclusionReason::NonceStale | ExclusionReason::InsufficientBalance)
+            );
+            if rechecked && tracked_tx.exclusion != reason {
+                self.set_exclusion(&tracked_tx.tx.hash, reason).await;
+            }
+        }
+    }
+
+    async fn set_status(&self, tx_hash: &str, status: TxStatus) {
+        if self.mempool_state.set_status(tx_hash, status.clone()).await {
+            if let Err(e) = self.db.update_tx_status(tx_hash, status.as_str(), None).await {
+                eprintln!("Failed to update status of {}: {:?}", tx_hash, e);
+            }
+        }
+    }
+
+    async fn set_exclusion(&self, tx_hash: &str, reason: Option<ExclusionReason>) {
+        if self.mempool_state.set_exclusion(tx_hash, reason).await {
+            let reason = reason.as_ref().map(ExclusionReason::as_str);
+            if let Err(e) = self.db.update_exclusion_reason(tx_hash, reason).await {
+                eprintln!("Failed to update exclusion reason of {}: {:?}", tx_hash, e);
+            }
+        }
+    }
+}
+
+// Most the sender can be charged for a tx, blob gas included
+fn max_cost(tracked_tx: &TrackedTx) -> U256 {
+    let blob_gas = U256::from(tracked_tx.tx.blob_count as u64 * DATA_GAS_PER_BLOB);
+    U256::from(tracked_tx.tx.gas_limit)
+        .saturating_mul(tracked_tx.tx.max_fee)
+        .saturating_add(blob_gas.saturating_mul(tracked_tx.tx.max_fee_per_blob_gas))
+        .saturating_add(tracked_tx.tx.value)
+}
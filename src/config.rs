@@ -1,14 +1,138 @@
 use std::env;
+use alloy::eips::eip4844::{fake_exponential, BLOB_TX_MIN_BLOB_GASPRICE, DATA_GAS_PER_BLOB};
+use alloy::primitives::U256;
 use eyre::{Result, eyre};
 use dotenv::dotenv;
+use crate::types::MinedBlock;
+
+// How to talk to the node
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RpcTransport {
+    Ws,
+    Ipc,
+    Http,
+}
+
+impl RpcTransport {
+    // Guess the transport from the URL scheme, anything without a scheme is an IPC socket path
+    fn from_url(url: &str) -> Self {
+        if url.starts_with("ws://") || url.starts_with("wss://") {
+            RpcTransport::Ws
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            RpcTransport::Http
+        } else {
+            RpcTransport::Ipc
+        }
+    }
+}
+
+// A node we read the mempool (and, for the first one, blocks) from
+#[derive(Clone, Debug)]
+pub struct NodeEndpoint {
+    pub label: String,
+    pub url: String,
+    pub transport: RpcTransport,
+}
+
+impl NodeEndpoint {
+    // Label nodes by host (or socket file) so API keys in the URL don't end up in the DB
+    fn new(url: &str, index: usize, transport: Option<RpcTransport>) -> Self {
+        let host = match url.split_once("://") {
+            Some((_, rest)) => rest.split(['/', '?']).next().unwrap_or(rest),
+            None => url.rsplit('/').next().unwrap_or(url),
+        };
+
+        Self {
+            label: format!("{}:{}", index, host),
+            url: url.to_string(),
+            transport: transport.unwrap_or_else(|| RpcTransport::from_url(url)),
+        }
+    }
+}
+
+// Where the "competitive tip" threshold comes from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThresholdSource {
+    // Percentile of the pending txs' effective tips
+    Mempool,
+    // Low percentile of the effective tips actually included in recent blocks
+    Inclusion,
+}
+
+// Blob fee market parameters of the active fork (EIP-4844, raised by EIP-7691 and the
+// blob-parameter-only forks of EIP-7892)
+#[derive(Clone, Copy, Debug)]
+pub struct BlobParams {
+    pub target_per_block: u64,
+    pub max_per_block: u64,
+    pub update_fraction: u128,
+    // EIP-7918 reserve price: blob gas costs at least base_cost execution gas, 0 before Fusaka
+    pub base_cost: u64,
+}
+
+impl BlobParams {
+    pub fn base_fee(&self, excess_blob_gas: u64) -> U256 {
+        U256::from(fake_exponential(BLOB_TX_MIN_BLOB_GASPRICE, excess_blob_gas as u128, self.update_fraction))
+    }
+
+    // Excess blob gas of the block following `block`
+    pub fn next_excess_blob_gas(&self, block: &MinedBlock) -> u64 {
+        let target = self.target_per_block * DATA_GAS_PER_BLOB;
+        let excess = block.excess_blob_gas + block.blob_gas_used;
+        if excess < target {
+            return 0;
+        }
+
+        // EIP-7918: while blobs are priced below the reserve, the fee can only go up
+        let below_reserve = U256::from(self.base_cost) * block.base_fee
+            > U256::from(DATA_GAS_PER_BLOB) * block.blob_base_fee;
+        if below_reserve {
+            let max = self.max_per_block.max(1);
+            block.excess_blob_gas + block.blob_gas_used * self.max_per_block.saturating_sub(self.target_per_block) / max
+        } else {
+            excess - target
+        }
+    }
+
+    // Blobs `block` could still have taken
+    pub fn remaining_blobs(&self, block: &MinedBlock) -> u64 {
+        self.max_per_block.saturating_sub(block.blob_gas_used / DATA_GAS_PER_BLOB)
+    }
+}
 
 #[derive(Clone)]
 pub struct Config {
-    pub rpc_url: String,
+    // First node is the primary, used for blocks and txpool polling
+    pub nodes: Vec<NodeEndpoint>,
+    pub min_seen_by_nodes: usize,
+    pub http_poll_interval_ms: u64,
+    pub full_pending_txs: bool,
+    pub fetch_concurrency: usize,
+    pub fetch_batch_size: usize,
+    pub txpool_source: bool,
+    pub txpool_poll_secs: u64,
     pub db_path: String,
     pub fee_percentile_threshold: f64,
+    pub threshold_source: ThresholdSource,
+    pub inclusion_window_blocks: usize,
+    pub inclusion_tip_percentile: f64,
     pub min_pending_blocks: u64,
     pub min_pending_seconds: i64,
+    pub validate_nonces: bool,
+    pub validate_balances: bool,
+    pub simulate_candidates: bool,
+    pub blob_params: BlobParams,
+    pub report_interval_blocks: u64,
+    // MEV-Boost relay data APIs used to attribute blocks, or a local file standing in for them
+    pub relay_urls: Vec<String>,
+    pub relay_mock_file: Option<String>,
+    // Beacon node API used to attribute blocks to their proposer
+    pub beacon_url: Option<String>,
+    // Address list files txs are matched against
+    pub sanctions_files: Vec<String>,
+    pub mempool_max_age_secs: i64,
+    pub db_retention_days: i64,
+    pub cleanup_interval_secs: u64,
 }
 
 impl Config {
@@ -16,16 +140,103 @@ impl Config {
         // Load .env file
         dotenv().ok();
 
-        let rpc_url = env::var("RPC_URL")
-            .map_err(|_| eyre!("RPC_URL must be set in .env file"))?;
+        // RPC_URLS is a comma separated list of nodes, RPC_URL a single one
+        let rpc_urls = env::var("RPC_URLS")
+            .or_else(|_| env::var("RPC_URL"))
+            .map_err(|_| eyre!("RPC_URL or RPC_URLS must be set in .env file"))?;
+
+        let rpc_transport = match env::var("RPC_TRANSPORT") {
+            Ok(kind) => match kind.to_lowercase().as_str() {
+                "ws" => Some(RpcTransport::Ws),
+                "ipc" => Some(RpcTransport::Ipc),
+                "http" => Some(RpcTransport::Http),
+                _ => return Err(eyre!("RPC_TRANSPORT must be one of ws, ipc, http")),
+            },
+            Err(_) => None,
+        };
+
+        let nodes: Vec<NodeEndpoint> = rpc_urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .enumerate()
+            .map(|(index, url)| NodeEndpoint::new(url, index, rpc_transport))
+            .collect();
+        if nodes.is_empty() {
+            return Err(eyre!("RPC_URLS must contain at least one URL"));
+        }
+
+        let min_seen_by_nodes: usize = env::var("MIN_SEEN_BY_NODES")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse()
+            .map_err(|_| eyre!("MIN_SEEN_BY_NODES must be a valid usize"))?;
+        if min_seen_by_nodes > nodes.len() {
+            return Err(eyre!(
+                "MIN_SEEN_BY_NODES ({}) can't exceed the number of nodes ({})",
+                min_seen_by_nodes,
+                nodes.len()
+            ));
+        }
+
+        let http_poll_interval_ms = env::var("HTTP_POLL_INTERVAL_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse()
+            .map_err(|_| eyre!("HTTP_POLL_INTERVAL_MS must be a valid u64"))?;
+
+        let full_pending_txs = env::var("FULL_PENDING_TXS")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .map_err(|_| eyre!("FULL_PENDING_TXS must be true or false"))?;
+
+        let fetch_concurrency = env::var("FETCH_CONCURRENCY")
+            .unwrap_or_else(|_| "16".to_string())
+            .parse()
+            .map_err(|_| eyre!("FETCH_CONCURRENCY must be a valid usize"))?;
+
+        let fetch_batch_size = env::var("FETCH_BATCH_SIZE")
+            .unwrap_or_else(|_| "50".to_string())
+            .parse()
+            .map_err(|_| eyre!("FETCH_BATCH_SIZE must be a valid usize"))?;
+
+        let txpool_source = env::var("TXPOOL_SOURCE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .map_err(|_| eyre!("TXPOOL_SOURCE must be true or false"))?;
+
+        let txpool_poll_secs = env::var("TXPOOL_POLL_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .map_err(|_| eyre!("TXPOOL_POLL_SECS must be a valid u64"))?;
 
         let db_path = env::var("DB_PATH")
             .unwrap_or_else(|_| "censorship.db".to_string());
 
-        let fee_percentile_threshold = env::var("FEE_PERCENTILE_THRESHOLD")
+        let fee_percentile_threshold: f64 = env::var("FEE_PERCENTILE_THRESHOLD")
             .unwrap_or_else(|_| "0.25".to_string())
             .parse()
             .map_err(|_| eyre!("FEE_PERCENTILE_THRESHOLD must be a valid f64"))?;
+        if !(0.0..=1.0).contains(&fee_percentile_threshold) {
+            return Err(eyre!("FEE_PERCENTILE_THRESHOLD must be between 0 and 1"));
+        }
+
+        let threshold_source = match env::var("THRESHOLD_SOURCE").unwrap_or_else(|_| "mempool".to_string()).as_str() {
+            "mempool" => ThresholdSource::Mempool,
+            "inclusion" => ThresholdSource::Inclusion,
+            other => return Err(eyre!("THRESHOLD_SOURCE must be mempool or inclusion, got {}", other)),
+        };
+
+        let inclusion_window_blocks = env::var("INCLUSION_WINDOW_BLOCKS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .map_err(|_| eyre!("INCLUSION_WINDOW_BLOCKS must be a valid usize"))?;
+
+        let inclusion_tip_percentile: f64 = env::var("INCLUSION_TIP_PERCENTILE")
+            .unwrap_or_else(|_| "0.05".to_string())
+            .parse()
+            .map_err(|_| eyre!("INCLUSION_TIP_PERCENTILE must be a valid f64"))?;
+        if !(0.0..=1.0).contains(&inclusion_tip_percentile) {
+            return Err(eyre!("INCLUSION_TIP_PERCENTILE must be between 0 and 1"));
+        }
 
         let min_pending_blocks = env::var("MIN_PENDING_BLOCKS")
             .unwrap_or_else(|_| "3".to_string())
@@ -37,12 +248,161 @@ impl Config {
             .parse()
             .map_err(|_| eyre!("MIN_PENDING_SECONDS must be a valid i64"))?;
 
+        let validate_nonces = env::var("VALIDATE_NONCES")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .map_err(|_| eyre!("VALIDATE_NONCES must be true or false"))?;
+
+        let validate_balances = env::var("VALIDATE_BALANCES")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .map_err(|_| eyre!("VALIDATE_BALANCES must be true or false"))?;
+
+        let simulate_candidates = env::var("SIMULATE_CANDIDATES")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .map_err(|_| eyre!("SIMULATE_CANDIDATES must be true or false"))?;
+
+        // Defaults are the mainnet values since the second blob-parameter-only fork (BPO2)
+        let blob_params = BlobParams {
+            target_per_block: env::var("BLOB_TARGET_PER_BLOCK")
+                .unwrap_or_else(|_| "14".to_string())
+                .parse()
+                .map_err(|_| eyre!("BLOB_TARGET_PER_BLOCK must be a valid u64"))?,
+            max_per_block: env::var("BLOB_MAX_PER_BLOCK")
+                .unwrap_or_else(|_| "21".to_string())
+                .parse()
+                .map_err(|_| eyre!("BLOB_MAX_PER_BLOCK must be a valid u64"))?,
+            update_fraction: env::var("BLOB_BASE_FEE_UPDATE_FRACTION")
+                .unwrap_or_else(|_| "11684671".to_string())
+                .parse()
+                .map_err(|_| eyre!("BLOB_BASE_FEE_UPDATE_FRACTION must be a valid u128"))?,
+            base_cost: env::var("BLOB_BASE_COST")
+                .unwrap_or_else(|_| "8192".to_string())
+                .parse()
+                .map_err(|_| eyre!("BLOB_BASE_COST must be a valid u64"))?,
+        };
+
+        let report_interval_blocks = env::var("REPORT_INTERVAL_BLOCKS")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse()
+            .map_err(|_| eyre!("REPORT_INTERVAL_BLOCKS must be a valid u64"))?;
+
+        let relay_urls = env::var("RELAY_URLS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let relay_mock_file = env::var("RELAY_MOCK_FILE").ok();
+
+        let beacon_url = env::var("BEACON_URL").ok();
+
+        let sanctions_files = env::var("SANCTIONS_FILES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mempool_max_age_secs = env::var("MEMPOOL_MAX_AGE_SECS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse()
+            .map_err(|_| eyre!("MEMPOOL_MAX_AGE_SECS must be a valid i64"))?;
+
+        let db_retention_days = env::var("DB_RETENTION_DAYS")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse()
+            .map_err(|_| eyre!("DB_RETENTION_DAYS must be a valid i64"))?;
+
+        let cleanup_interval_secs = env::var("CLEANUP_INTERVAL_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .map_err(|_| eyre!("CLEANUP_INTERVAL_SECS must be a valid u64"))?;
+
         Ok(Config {
-            rpc_url,
+            nodes,
+            min_seen_by_nodes,
+            http_poll_interval_ms,
+            full_pending_txs,
+            fetch_concurrency,
+            fetch_batch_size,
+            txpool_source,
+            txpool_poll_secs,
             db_path,
             fee_percentile_threshold,
+            threshold_source,
+            inclusion_window_blocks,
+            inclusion_tip_percentile,
             min_pending_blocks,
             min_pending_seconds,
+            validate_nonces,
+            validate_balances,
+            simulate_candidates,
+            blob_params,
+            report_interval_blocks,
+            relay_urls,
+            relay_mock_file,
+            beacon_url,
+            sanctions_files,
+            mempool_max_age_secs,
+            db_retention_days,
+            cleanup_interval_secs,
         })
     }
+
+    pub fn primary_node(&self) -> &NodeEndpoint {
+        &self.nodes[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: BlobParams = BlobParams {
+        target_per_block: 14,
+        max_per_block: 21,
+        update_fraction: 11684671,
+        base_cost: 8192,
+    };
+
+    fn parent(base_fee: u64, excess_blob_gas: u64, blobs: u64) -> MinedBlock {
+        MinedBlock {
+            base_fee: U256::from(base_fee),
+            blob_gas_used: blobs * DATA_GAS_PER_BLOB,
+            excess_blob_gas,
+            blob_base_fee: PARAMS.base_fee(excess_blob_gas),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn excess_blob_gas_resets_below_target() {
+        assert_eq!(PARAMS.next_excess_blob_gas(&parent(1_000_000_000, 0, 13)), 0);
+    }
+
+    #[test]
+    fn excess_blob_gas_above_reserve_moves_by_distance_to_target() {
+        // Blob base fee 1 wei, 8192 * 1 wei of execution gas is below a blob's 131072 * 1 wei
+        let block = parent(1, 5_000_000, 16);
+        assert_eq!(block.blob_base_fee, U256::from(1));
+        assert_eq!(PARAMS.next_excess_blob_gas(&block), 5_000_000 + 2 * DATA_GAS_PER_BLOB);
+    }
+
+    #[test]
+    fn excess_blob_gas_below_reserve_only_rises() {
+        // 8192 * 1 gwei of execution gas outweighs a blob priced at 1 wei, so EIP-7918 adds
+        // blob_gas_used * (max - target) / max instead of subtracting the target
+        let block = parent(1_000_000_000, 5_000_000, 16);
+        assert_eq!(PARAMS.next_excess_blob_gas(&block), 5_000_000 + 16 * DATA_GAS_PER_BLOB * 7 / 21);
+        assert_eq!(PARAMS.next_excess_blob_gas(&block), 5_699_050);
+
+        // Even an empty block can't lower it
+        let block = parent(1_000_000_000, 5_000_000, 0);
+        assert_eq!(PARAMS.next_excess_blob_gas(&block), 5_000_000);
+    }
 }
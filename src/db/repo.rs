@@ -1,8 +1,8 @@
   use rusqlite::{Connection, params};
   use std::sync::Arc;
   use tokio::sync::Mutex;
-  use eyre::Result;
-  use crate::types::{PendingTx, CensorshipEvent, MinedBlock, MempoolSnapshot};
+  use eyre::{Result, eyre};
+  use crate::types::{BuilderScore, CensorshipRate, Inclusion, PendingTx, Reorg, CensorshipEvent, MinedBlock, MempoolSnapshot, VisibilityGap, TxStatus};
 
   pub struct Repository {
       conn: Arc<Mutex<Connection>>,
@@ -11,6 +11,7 @@
   impl Repository {
       pub async fn new(db_path: &str) -> Result<Self> {
           let conn = Connection::open(db_path)?;
+          conn.query_row("PRAGMA journal_mode=WAL", [], |_| Ok(()))?;
           let repo = Self {
               conn: Arc::new(Mutex::new(conn)),
           };
@@ -28,7 +29,7 @@
                   tx_hash TEXT NOT NULL UNIQUE,
                   from_address TEXT NOT NULL,
                   to_address TEXT,
-                  max_priority_fee TEXT NOT NULL,
+                  max_priority_fee TEXT,
                   max_fee TEXT NOT NULL,
                   nonce INTEGER NOT NULL,
                   gas_limit INTEGER NOT NULL,
@@ -38,10 +39,21 @@
                   status TEXT NOT NULL,
                   included_in_block INTEGER,
                   last_updated INTEGER NOT NULL,
-                  CHECK(status IN ('pending', 'included', 'dropped', 'censored'))
+                  replaced_by TEXT,
+                  exclusion_reason TEXT,
+                  tx_type INTEGER,
+                  max_fee_per_blob_gas TEXT,
+                  blob_count INTEGER,
+                  delegations TEXT,
+                  included_at INTEGER,
+                  inclusion_latency_blocks INTEGER,
+                  inclusion_latency_secs INTEGER,
+                  sanctions TEXT,
+                  gas_price TEXT
               )",
               [],
           )?;
+          migrate_drop_status_check(&conn)?;
 
           // Create censorship events table
           conn.execute(
@@ -56,8 +68,17 @@
                   blocks_pending INTEGER NOT NULL,
                   seconds_pending INTEGER NOT NULL,
                   confidence_score REAL NOT NULL,
+                  seen_by_nodes INTEGER NOT NULL DEFAULT 1,
                   detected_at_block INTEGER NOT NULL,
                   detected_at INTEGER NOT NULL,
+                  sim_gas_used INTEGER,
+                  sim_includable INTEGER,
+                  sim_reverted INTEGER,
+                  sim_revert_reason TEXT,
+                  orphaned INTEGER NOT NULL DEFAULT 0,
+                  skipped_by TEXT,
+                  skipped_by_proposers TEXT,
+                  sim_sanctions TEXT,
                   FOREIGN KEY(tx_hash) REFERENCES transactions(tx_hash)
               )",
               [],
@@ -72,7 +93,21 @@
                   gas_used INTEGER NOT NULL,
                   gas_limit INTEGER NOT NULL,
                   tx_count INTEGER NOT NULL,
-                  created_at INTEGER NOT NULL
+                  created_at INTEGER NOT NULL,
+                  block_hash TEXT,
+                  parent_hash TEXT,
+                  blob_gas_used INTEGER,
+                  excess_blob_gas INTEGER,
+                  blob_base_fee TEXT,
+                  fee_recipient TEXT,
+                  builder_tag TEXT,
+                  extra_data TEXT,
+                  relay TEXT,
+                  builder_pubkey TEXT,
+                  slot INTEGER,
+                  proposer_index INTEGER,
+                  flagged_eligible INTEGER NOT NULL DEFAULT 0,
+                  flagged_skipped INTEGER NOT NULL DEFAULT 0
               )",
               [],
           )?;
@@ -87,11 +122,91 @@
                   p50_fee TEXT NOT NULL,
                   p75_fee TEXT NOT NULL,
                   p90_fee TEXT NOT NULL,
-                  tx_count INTEGER NOT NULL
+                  tx_count INTEGER NOT NULL,
+                  base_fee TEXT,
+                  threshold_fee TEXT
+              )",
+              [],
+          )?;
+
+          // Create visibility gaps table
+          conn.execute(
+              "CREATE TABLE IF NOT EXISTS visibility_gaps (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  source TEXT NOT NULL,
+                  node TEXT NOT NULL DEFAULT '',
+                  started_at INTEGER NOT NULL,
+                  ended_at INTEGER NOT NULL
               )",
               [],
           )?;
 
+          // Create per-node sightings table
+          conn.execute(
+              "CREATE TABLE IF NOT EXISTS tx_sightings (
+                  tx_hash TEXT NOT NULL,
+                  node TEXT NOT NULL,
+                  first_seen INTEGER NOT NULL,
+                  PRIMARY KEY(tx_hash, node)
+              )",
+              [],
+          )?;
+
+          // Create reorgs table
+          conn.execute(
+              "CREATE TABLE IF NOT EXISTS reorgs (
+                  id INTEGER PRIMARY KEY AUTOINCREMENT,
+                  detected_at INTEGER NOT NULL,
+                  common_ancestor INTEGER NOT NULL,
+                  depth INTEGER NOT NULL,
+                  orphaned_hashes TEXT NOT NULL,
+                  affected_txs TEXT NOT NULL,
+                  affected_tx_count INTEGER NOT NULL
+              )",
+              [],
+          )?;
+
+          // Columns added after the initial schema
+          add_column_if_missing(&conn, "censorship_events", "seen_by_nodes", "INTEGER NOT NULL DEFAULT 1")?;
+          add_column_if_missing(&conn, "visibility_gaps", "node", "TEXT NOT NULL DEFAULT ''")?;
+          add_column_if_missing(&conn, "transactions", "replaced_by", "TEXT")?;
+          add_column_if_missing(&conn, "transactions", "exclusion_reason", "TEXT")?;
+          add_column_if_missing(&conn, "transactions", "tx_type", "INTEGER")?;
+          add_column_if_missing(&conn, "transactions", "max_fee_per_blob_gas", "TEXT")?;
+          add_column_if_missing(&conn, "transactions", "blob_count", "INTEGER")?;
+          add_column_if_missing(&conn, "transactions", "delegations", "TEXT")?;
+          add_column_if_missing(&conn, "transactions", "included_at", "INTEGER")?;
+          add_column_if_missing(&conn, "transactions", "inclusion_latency_blocks", "INTEGER")?;
+          add_column_if_missing(&conn, "transactions", "inclusion_latency_secs", "INTEGER")?;
+          add_column_if_missing(&conn, "transactions", "sanctions", "TEXT")?;
+          add_column_if_missing(&conn, "transactions", "gas_price", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "block_hash", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "parent_hash", "TEXT")?;
+          add_column_if_missing(&conn, "censorship_events", "orphaned", "INTEGER NOT NULL DEFAULT 0")?;
+          add_column_if_missing(&conn, "blocks", "fee_recipient", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "builder_tag", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "extra_data", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "relay", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "builder_pubkey", "TEXT")?;
+          add_column_if_missing(&conn, "censorship_events", "skipped_by", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "slot", "INTEGER")?;
+          add_column_if_missing(&conn, "blocks", "proposer_index", "INTEGER")?;
+          add_column_if_missing(&conn, "censorship_events", "skipped_by_proposers", "TEXT")?;
+          add_column_if_missing(&conn, "blocks", "flagged_eligible", "INTEGER NOT NULL DEFAULT 0")?;
+          add_column_if_missing(&conn, "blocks", "flagged_skipped", "INTEGER NOT NULL DEFAULT 0")?;
+          add_column_if_missing(&conn, "blocks", "blob_gas_used", "INTEGER")?;
+          add_column_if_missing(&conn, "blocks", "excess_blob_gas", "INTEGER")?;
+          add_column_if_missing(&conn, "blocks", "blob_base_fee", "TEXT")?;
+          add_column_if_missing(&conn, "mempool_snapshots", "base_fee", "TEXT")?;
+          add_column_if_missing(&conn, "mempool_snapshots", "threshold_fee", "TEXT")?;
+          add_column_if_missing(&conn, "censorship_events", "sim_gas_used", "INTEGER")?;
+          add_column_if_missing(&conn, "censorship_events", "sim_includable", "INTEGER")?;
+          add_column_if_missing(&conn, "censorship_events", "sim_reverted", "INTEGER")?;
+          add_column_if_missing(&conn, "censorship_events", "sim_revert_reason", "TEXT")?;
+          add_column_if_missing(&conn, "censorship_events", "sim_sanctions", "TEXT")?;
+
+          migrate_nullable_priority_fee(&conn)?;
+
           // Create indexes
           conn.execute(
               "CREATE INDEX IF NOT EXISTS idx_tx_status ON transactions(status)",
@@ -105,6 +220,10 @@
               "CREATE INDEX IF NOT EXISTS idx_censorship_detected_at ON censorship_events(detected_at)",
               [],
           )?;
+          conn.execute(
+              "CREATE INDEX IF NOT EXISTS idx_censorship_tx_hash ON censorship_events(tx_hash)",
+              [],
+          )?;
 
           Ok(())
       }
@@ -116,27 +235,50 @@
               "INSERT OR IGNORE INTO transactions (
                   tx_hash, from_address, to_address, max_priority_fee, max_fee,
                   nonce, gas_limit, value, input_data_size, first_seen,
-                  status, last_updated
-              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                  status, last_updated, tx_type, max_fee_per_blob_gas, blob_count,
+                  delegations, sanctions, gas_price
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
               params![
                   tx.hash,
                   tx.from.to_string(),
                   tx.to.map(|a| a.to_string()),
-                  tx.max_priority_fee.to_string(),
+                  // Legacy and access-list txs have no tip cap, only a gas price (stored in max_fee and gas_price)
+                  (!tx.has_gas_price_only()).then(|| tx.max_priority_fee.to_string()),
                   tx.max_fee.to_string(),
                   tx.nonce,
                   tx.gas_limit,
                   tx.value.to_string(),
                   tx.input_data_size,
                   tx.first_seen,
-                  "pending",
+                  if tx.queued { TxStatus::Queued.as_str() } else { TxStatus::Pending.as_str() },
                   tx.first_seen,
+                  tx.tx_type,
+                  tx.max_fee_per_blob_gas.to_string(),
+                  tx.blob_count,
+                  // Comma-separated delegate addresses, NULL for non set-code txs
+                  (!tx.delegations.is_empty()).then(|| {
+                      tx.delegations.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(",")
+                  }),
+                  // Comma-separated names of the matching sanctions lists, NULL if none
+                  (!tx.sanctions.is_empty()).then(|| tx.sanctions.join(",")),
+                  tx.has_gas_price_only().then(|| tx.max_fee.to_string()),
               ],
           )?;
 
           Ok(())
       }
 
+      pub async fn insert_sighting(&self, tx: &PendingTx) -> Result<()> {
+          let conn = self.conn.lock().await;
+
+          conn.execute(
+              "INSERT OR IGNORE INTO tx_sightings (tx_hash, node, first_seen) VALUES (?1, ?2, ?3)",
+              params![tx.hash, tx.seen_by, tx.first_seen],
+          )?;
+
+          Ok(())
+      }
+
       pub async fn update_tx_status(
           &self,
           hash: &str,
@@ -158,15 +300,85 @@
           Ok(())
       }
 
-      pub async fn insert_censorship_event(&self, event: &CensorshipEvent) -> Result<()> {
+      // Record a block's inclusions in one transaction, blocks can include hundreds of tracked txs
+      pub async fn mark_included(&self, inclusions: &[Inclusion]) -> Result<()> {
+          let conn = self.conn.lock().await;
+          let now = std::time::SystemTime::now()
+              .duration_since(std::time::UNIX_EPOCH)?
+              .as_secs() as i64;
+
+          let db_tx = conn.unchecked_transaction()?;
+          {
+              let mut stmt = db_tx.prepare(
+                  "UPDATE transactions
+                   SET status = ?1, included_in_block = ?2, included_at = ?3,
+                       inclusion_latency_blocks = ?4, inclusion_latency_secs = ?5, last_updated = ?6
+                   WHERE tx_hash = ?7",
+              )?;
+              for inclusion in inclusions {
+                  stmt.execute(params![
+                      TxStatus::Included { block_number: inclusion.block_number }.as_str(),
+                      inclusion.block_number,
+                      inclusion.included_at,
+                      inclusion.latency_blocks,
+                      inclusion.latency_secs,
+                      now,
+                      inclusion.tx_hash,
+                  ])?;
+              }
+          }
+          db_tx.commit()?;
+
+          Ok(())
+      }
+
+      pub async fn update_exclusion_reason(&self, hash: &str, reason: Option<&str>) -> Result<()> {
           let conn = self.conn.lock().await;
+          let now = std::time::SystemTime::now()
+              .duration_since(std::time::UNIX_EPOCH)?
+              .as_secs() as i64;
 
           conn.execute(
+              "UPDATE transactions SET exclusion_reason = ?1, last_updated = ?2 WHERE tx_hash = ?3",
+              params![reason, now, hash],
+          )?;
+
+          Ok(())
+      }
+
+      // Link a superseded transaction to the one that replaced it
+      pub async fn mark_replaced(&self, hash: &str, replaced_by: &str) -> Result<()> {
+          let conn = self.conn.lock().await;
+          let now = std::time::SystemTime::now()
+              .duration_since(std::time::UNIX_EPOCH)?
+              .as_secs() as i64;
+
+          conn.execute(
+              "UPDATE transactions
+               SET status = ?1, replaced_by = ?2, last_updated = ?3
+               WHERE tx_hash = ?4",
+              params![TxStatus::Replaced { by: replaced_by.to_string() }.as_str(), replaced_by, now, hash],
+          )?;
+
+          Ok(())
+      }
+
+      // Store the event and flag its transaction together, under a single lock
+      pub async fn insert_censorship_event(&self, event: &CensorshipEvent) -> Result<()> {
+          let conn = self.conn.lock().await;
+          let now = std::time::SystemTime::now()
+              .duration_since(std::time::UNIX_EPOCH)?
+              .as_secs() as i64;
+
+          let db_tx = conn.unchecked_transaction()?;
+          db_tx.execute(
               "INSERT INTO censorship_events (
                   tx_hash, from_address, to_address, priority_fee, threshold_fee,
                   fee_percentile, blocks_pending, seconds_pending, confidence_score,
-                  detected_at_block, detected_at
-              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                  seen_by_nodes, detected_at_block, detected_at, sim_gas_used,
+                  sim_includable, sim_reverted, sim_revert_reason, skipped_by,
+                  skipped_by_proposers, sim_sanctions
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
               params![
                   event.tx_hash,
                   event.from.to_string(),
@@ -177,13 +389,27 @@
                   event.blocks_pending,
                   event.seconds_pending,
                   event.confidence_score,
+                  event.seen_by_nodes,
                   event.detected_at_block,
                   event.detected_at,
+                  event.simulation.as_ref().map(|sim| sim.gas_used),
+                  event.simulation.as_ref().map(|sim| sim.includable),
+                  event.simulation.as_ref().map(|sim| sim.reverted),
+                  event.simulation.as_ref().and_then(|sim| sim.revert_reason.clone()),
+                  event.skipped_by.join(","),
+                  event.skipped_by_proposers.iter().map(u64::to_string).collect::<Vec<_>>().join(","),
+                  (!event.sim_sanctions.is_empty()).then(|| event.sim_sanctions.join(",")),
               ],
           )?;
 
           // Also update the transaction status to 'censored'
-          self.update_tx_status(&event.tx_hash, "censored", None).await?;
+          db_tx.execute(
+              "UPDATE transactions
+               SET status = ?1, included_in_block = NULL, last_updated = ?2
+               WHERE tx_hash = ?3",
+              params![TxStatus::PotentiallyCensored.as_str(), now, event.tx_hash],
+          )?;
+          db_tx.commit()?;
 
           Ok(())
       }
@@ -197,8 +423,10 @@
           conn.execute(
               "INSERT OR REPLACE INTO blocks (
                   block_number, timestamp, base_fee, gas_used, gas_limit,
-                  tx_count, created_at
-              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                  tx_count, created_at, blob_gas_used, excess_blob_gas, blob_base_fee,
+                  block_hash, parent_hash, fee_recipient, builder_tag, extra_data, relay, builder_pubkey,
+                  slot, proposer_index
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
               params![
                   block.number,
                   block.timestamp,
@@ -207,12 +435,75 @@
                   block.gas_limit as i64,
                   block.tx_hashes.len(),
                   now,
+                  block.blob_gas_used,
+                  block.excess_blob_gas,
+                  block.blob_base_fee.to_string(),
+                  block.hash.to_string(),
+                  block.parent_hash.to_string(),
+                  block.fee_recipient.to_string(),
+                  block.builder_tag(),
+                  block.extra_data.to_string(),
+                  block.relay,
+                  block.builder_pubkey,
+                  block.slot,
+                  block.proposer_index,
               ],
           )?;
 
           Ok(())
       }
 
+      // How many flagged txs `block_number` could have included, and how many of those it left out
+      pub async fn update_block_flagged(&self, block_number: u64, eligible: u64, skipped: u64) -> Result<()> {
+          let conn = self.conn.lock().await;
+
+          conn.execute(
+              "UPDATE blocks SET flagged_eligible = ?1, flagged_skipped = ?2 WHERE block_number = ?3",
+              params![eligible, skipped, block_number],
+          )?;
+
+          Ok(())
+      }
+
+      // Log a reorg and undo what we recorded for the orphaned blocks
+      pub async fn record_reorg(&self, reorg: &Reorg, affected_txs: &[String]) -> Result<()> {
+          let conn = self.conn.lock().await;
+          let orphaned_hashes: Vec<String> = reorg.orphaned.iter().map(|b| b.hash.to_string()).collect();
+
+          let db_tx = conn.unchecked_transaction()?;
+          db_tx.execute(
+              "INSERT INTO reorgs (
+                  detected_at, common_ancestor, depth, orphaned_hashes, affected_txs, affected_tx_count
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+              params![
+                  reorg.detected_at,
+                  reorg.common_ancestor,
+                  reorg.orphaned.len(),
+                  orphaned_hashes.join(","),
+                  affected_txs.join(","),
+                  affected_txs.len(),
+              ],
+          )?;
+          db_tx.execute(
+              "UPDATE transactions
+               SET status = ?1, included_in_block = NULL, included_at = NULL,
+                   inclusion_latency_blocks = NULL, inclusion_latency_secs = NULL, last_updated = ?2
+               WHERE included_in_block > ?3",
+              params![TxStatus::Pending.as_str(), reorg.detected_at, reorg.common_ancestor],
+          )?;
+          db_tx.execute(
+              "UPDATE censorship_events SET orphaned = 1 WHERE detected_at_block > ?1",
+              params![reorg.common_ancestor],
+          )?;
+          db_tx.execute(
+              "DELETE FROM blocks WHERE block_number > ?1",
+              params![reorg.common_ancestor],
+          )?;
+          db_tx.commit()?;
+
+          Ok(())
+      }
+
       pub async fn insert_snapshot(
           &self,
           snapshot: &MempoolSnapshot,
@@ -222,8 +513,9 @@
 
           conn.execute(
               "INSERT INTO mempool_snapshots (
-                  timestamp, block_number, p25_fee, p50_fee, p75_fee, p90_fee, tx_count
-              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                  timestamp, block_number, p25_fee, p50_fee, p75_fee, p90_fee, tx_count, base_fee,
+                  threshold_fee
+              ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
               params![
                   snapshot.timestamp,
                   block,
@@ -232,12 +524,124 @@
                   snapshot.fee_percentiles.p75.to_string(),
                   snapshot.fee_percentiles.p90.to_string(),
                   snapshot.tx_count,
+                  snapshot.base_fee.to_string(),
+                  snapshot.threshold_fee.to_string(),
               ],
           )?;
 
           Ok(())
       }
 
+      pub async fn insert_visibility_gap(&self, gap: &VisibilityGap) -> Result<()> {
+          let conn = self.conn.lock().await;
+
+          conn.execute(
+              "INSERT INTO visibility_gaps (source, node, started_at, ended_at) VALUES (?1, ?2, ?3, ?4)",
+              params![gap.source, gap.node, gap.started_at, gap.ended_at],
+          )?;
+
+          Ok(())
+      }
+
+      // Checkpoint the WAL so everything written so far lands in the main db file
+      pub async fn flush(&self) -> Result<()> {
+          let conn = self.conn.lock().await;
+          conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+          Ok(())
+      }
+
+      // Share of tracked txs that were ever flagged, set-code (type 4) txs versus everything else
+      pub async fn censorship_rates_by_tx_type(&self) -> Result<Vec<CensorshipRate>> {
+          let conn = self.conn.lock().await;
+
+          let mut stmt = conn.prepare(
+              "SELECT
+                  CASE WHEN t.tx_type = 4 THEN 'set_code' ELSE 'other' END AS tx_class,
+                  COUNT(*),
+                  COUNT(e.tx_hash)
+              FROM transactions t
+              LEFT JOIN (SELECT DISTINCT tx_hash FROM censorship_events WHERE orphaned = 0) e
+                  ON e.tx_hash = t.tx_hash
+              GROUP BY tx_class
+              ORDER BY tx_class DESC",
+          )?;
+          let rates = stmt
+              .query_map([], |row| {
+                  Ok(CensorshipRate {
+                      tx_class: row.get(0)?,
+                      total: row.get(1)?,
+                      censored: row.get(2)?,
+                  })
+              })?
+              .collect::<Result<Vec<_>, _>>()?;
+
+          Ok(rates)
+      }
+
+      // Same as censorship_rates_by_tx_type, split by whether the tx matched a sanctions list
+      pub async fn censorship_rates_by_sanctions(&self) -> Result<Vec<CensorshipRate>> {
+          let conn = self.conn.lock().await;
+
+          let mut stmt = conn.prepare(
+              "SELECT
+                  CASE WHEN t.sanctions IS NOT NULL THEN 'sanctioned' ELSE 'non_sanctioned' END AS tx_class,
+                  COUNT(*),
+                  COUNT(e.tx_hash)
+              FROM transactions t
+              LEFT JOIN (SELECT DISTINCT tx_hash FROM censorship_events WHERE orphaned = 0) e
+                  ON e.tx_hash = t.tx_hash
+              GROUP BY tx_class
+              ORDER BY tx_class DESC",
+          )?;
+          let rates = stmt
+              .query_map([], |row| {
+                  Ok(CensorshipRate {
+                      tx_class: row.get(0)?,
+                      total: row.get(1)?,
+                      censored: row.get(2)?,
+                  })
+              })?
+              .collect::<Result<Vec<_>, _>>()?;
+
+          Ok(rates)
+      }
+
+      // Per fee recipient, over the stored blocks: how many were built while a flagged tx was
+      // eligible and how many of those skipped one. Worst offenders first.
+      pub async fn builder_scoreboard(&self) -> Result<Vec<BuilderScore>> {
+          let conn = self.conn.lock().await;
+
+          let mut stmt = conn.prepare(
+              "SELECT
+                  b.fee_recipient,
+                  (SELECT COALESCE(l.builder_tag, l.fee_recipient) FROM blocks l
+                   WHERE l.fee_recipient = b.fee_recipient
+                   ORDER BY l.block_number DESC LIMIT 1),
+                  COUNT(*),
+                  SUM(b.flagged_eligible > 0),
+                  SUM(b.flagged_skipped > 0)
+              FROM blocks b
+              WHERE b.fee_recipient IS NOT NULL
+              GROUP BY b.fee_recipient
+              HAVING SUM(b.flagged_eligible > 0) > 0
+              ORDER BY SUM(b.flagged_skipped > 0) * 1.0 / SUM(b.flagged_eligible > 0) DESC,
+                       SUM(b.flagged_eligible > 0) DESC",
+          )?;
+          let scores = stmt
+              .query_map([], |row| {
+                  Ok(BuilderScore {
+                      fee_recipient: row.get(0)?,
+                      builder: row.get(1)?,
+                      blocks: row.get(2)?,
+                      eligible_blocks: row.get(3)?,
+                      skipped_blocks: row.get(4)?,
+                  })
+              })?
+              .collect::<Result<Vec<_>, _>>()?;
+
+          Ok(scores)
+      }
+
       pub async fn cleanup_old_data(&self, retention_days: i64) -> Result<()> {
           let conn = self.conn.lock().await;
           let cutoff = std::time::SystemTime::now()
@@ -257,7 +661,106 @@
               params![cutoff],
           )?;
 
+          // Delete sightings of transactions that are gone
+          conn.execute(
+              "DELETE FROM tx_sightings WHERE tx_hash NOT IN (SELECT tx_hash FROM transactions)",
+              [],
+          )?;
+
+          // Delete old visibility gaps
+          conn.execute(
+              "DELETE FROM visibility_gaps WHERE ended_at < ?1",
+              params![cutoff],
+          )?;
+
           Ok(())
       }
   }
 
+  // Older databases restricted transactions.status with a CHECK constraint that doesn't know
+  // about newer statuses. SQLite can't alter constraints, so rebuild the table without it.
+  fn migrate_drop_status_check(conn: &Connection) -> Result<()> {
+      let sql: String = conn.query_row(
+          "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'transactions'",
+          [],
+          |row| row.get(0),
+      )?;
+      if !sql.contains("CHECK(status") {
+          return Ok(());
+      }
+
+      conn.execute_batch(
+          "BEGIN;
+           CREATE TABLE transactions_new (
+               id INTEGER PRIMARY KEY AUTOINCREMENT,
+               tx_hash TEXT NOT NULL UNIQUE,
+               from_address TEXT NOT NULL,
+               to_address TEXT,
+               max_priority_fee TEXT NOT NULL,
+               max_fee TEXT NOT NULL,
+               nonce INTEGER NOT NULL,
+               gas_limit INTEGER NOT NULL,
+               value TEXT NOT NULL,
+               input_data_size INTEGER NOT NULL,
+               first_seen INTEGER NOT NULL,
+               status TEXT NOT NULL,
+               included_in_block INTEGER,
+               last_updated INTEGER NOT NULL
+           );
+           INSERT INTO transactions_new SELECT * FROM transactions;
+           DROP TABLE transactions;
+           ALTER TABLE transactions_new RENAME TO transactions;
+           COMMIT;",
+      )?;
+
+      Ok(())
+  }
+
+  // Legacy and access-list txs used to store their gas price as max_priority_fee, which reads
+  // as a tip of the whole gas price. Rebuild the table with the column nullable and move those
+  // values to gas_price. Runs after the column additions, so the stored schema is complete.
+  fn migrate_nullable_priority_fee(conn: &Connection) -> Result<()> {
+      let sql: String = conn.query_row(
+          "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'transactions'",
+          [],
+          |row| row.get(0),
+      )?;
+      if !sql.contains("max_priority_fee TEXT NOT NULL") {
+          return Ok(());
+      }
+
+      // Same definition, minus the constraint, under the new name
+      let columns = &sql[sql.find('(').ok_or_else(|| eyre!("unexpected transactions schema"))?..];
+      let create = format!(
+          "CREATE TABLE transactions_new {}",
+          columns.replacen("max_priority_fee TEXT NOT NULL", "max_priority_fee TEXT", 1)
+      );
+
+      conn.execute_batch(&format!(
+          "BEGIN;
+           {};
+           INSERT INTO transactions_new SELECT * FROM transactions;
+           UPDATE transactions_new SET gas_price = max_fee, max_priority_fee = NULL WHERE tx_type IN (0, 1);
+           DROP TABLE transactions;
+           ALTER TABLE transactions_new RENAME TO transactions;
+           COMMIT;",
+          create
+      ))?;
+
+      Ok(())
+  }
+
+  fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+      let exists = conn
+          .prepare(&format!("PRAGMA table_info({})", table))?
+          .query_map([], |row| row.get::<_, String>(1))?
+          .collect::<rusqlite::Result<Vec<_>>>()?
+          .iter()
+          .any(|name| name == column);
+
+      if !exists {
+          conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+      }
+
+      Ok(())
+  }
@@ -3,120 +3,294 @@ pub mod types;
 pub mod network;
 pub mod db;
 pub mod analysis;
+pub mod supervisor;
 
+use std::sync::Arc;
+use std::time::Duration;
+use analysis::detector::CensorshipDetector;
+use analysis::sanctions::SanctionsLists;
 use config::Config;
+use db::repo::Repository;
 use eyre::Result;
-use tokio::sync::mpsc;
-use types::PendingTx;
+use network::beacon::BeaconClient;
+use network::blocks::BlockMonitor;
+use network::state::{AddOutcome, MempoolState};
+use supervisor::Supervisor;
+use tokio::sync::{mpsc, watch, Mutex};
+use types::{ChainEvent, PendingTx, TxPoolView, TxStatus, VisibilityGap};
 
 #[tokio::main]
-async fn main() -> Result<()>{
-
-    // TODO: Implement full channel-based architecture
-    // See the plan for details on how to wire everything together
-
-    // Minimal stub to make it compile
-    // let (tx_sender, mut tx_receiver) = mpsc::channel::<PendingTx>(1000);
-    //
-    // let mempool_handle = tokio::spawn({
-    //     let config = config.clone();
-    //     async move {
-    //         println!("   ↳ Spawning Mempool Watcher...");
-    //         if let Err(e) = network::mempool::spawn_monitor(config, tx_sender).await {
-    //             eprintln!("❌ Mempool Monitor Failed: {:?}", e);
-    //         }
-    //     }
-    // });
-    //
-    // // Receive and print transactions (temporary)
-    // let receiver_handle = tokio::spawn(async move {
-    //     while let Some(tx) = tx_receiver.recv().await {
-    //         println!("[NEW TX] Hash: {} | Priority Fee: {} wei", tx.hash, tx.max_priority_fee);
-    //     }
-    // });
-    
+async fn main() -> Result<()> {
     let config = Config::from_env()?;
 
-    // Init DB
+    // Init DB and shared state
     let db = Arc::new(Repository::new(&config.db_path).await?);
     let mempool_state = Arc::new(MempoolState::new());
+    let relays = network::relay::from_config(&config)?;
+    let sanctions = Arc::new(SanctionsLists::from_files(&config.sanctions_files)?);
+    let beacon = config.beacon_url.as_deref().map(BeaconClient::new).transpose()?.map(Arc::new);
     let detector = Arc::new(CensorshipDetector::new(
         mempool_state.clone(),
         db.clone(),
         config.clone(),
+        sanctions.clone(),
     ));
 
-    // Spawn channels
+    // Spawn channels. Receivers are shared so a restarted processor picks up where the last one stopped.
     let (tx_sender, tx_receiver) = mpsc::channel::<PendingTx>(1000);
-    let (block_sender, block_receiver) = mpsc::channel::<MinedBlock>(100);
+    let (block_sender, block_receiver) = mpsc::channel::<ChainEvent>(100);
+    let (gap_sender, gap_receiver) = mpsc::channel::<VisibilityGap>(100);
+    let tx_receiver = Arc::new(Mutex::new(tx_receiver));
+    let block_receiver = Arc::new(Mutex::new(block_receiver));
+    let (pool_sender, pool_receiver) = mpsc::channel::<TxPoolView>(10);
+    let gap_receiver = Arc::new(Mutex::new(gap_receiver));
+    let pool_receiver = Arc::new(Mutex::new(pool_receiver));
 
-    let mempool_handle = tokio::spawn({
+    let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+    let supervisor = Supervisor::new(shutdown_receiver);
+
+    println!("🚀 Starting CenCar...");
+
+    // One mempool monitor per configured node
+    let mempool_handles: Vec<_> = config.nodes.iter().map(|node| {
+        supervisor.spawn(format!("Mempool Monitor ({})", node.label), {
+            let config = config.clone();
+            let node = node.clone();
+            let tx_sender = tx_sender.clone();
+            let gap_sender = gap_sender.clone();
+            move || network::mempool::spawn_monitor(config.clone(), node.clone(), tx_sender.clone(), gap_sender.clone())
+        })
+    }).collect();
+
+    // Optional: seed and reconcile from txpool_content / txpool_inspect
+    let txpool_handle = config.txpool_source.then(|| {
+        supervisor.spawn("Txpool Poller", {
+            let config = config.clone();
+            let tx_sender = tx_sender.clone();
+            let pool_sender = pool_sender.clone();
+            move || network::txpool::spawn_poller(config.clone(), tx_sender.clone(), pool_sender.clone())
+        })
+    });
+    drop(tx_sender);
+    drop(pool_sender);
+
+    let block_handle = supervisor.spawn("Block Monitor", {
         let config = config.clone();
-        async move {
-            network::mempool::spawn_monitor(config, tx_sender).await
-        }
+        move || BlockMonitor::new(config.clone(), relays.clone(), beacon.clone()).spawn_monitor(block_sender.clone(), gap_sender.clone())
     });
 
-    let block_haandle = tokio::spawn({
+    let cleanup_handle = supervisor.spawn("Cleanup", {
         let config = config.clone();
-        async move {
-            let mut block_monitor = network::BlockMonitor::new(config);
-            block_monitor.spawn_monitor(tx_sender).await
-        }
+        let db = db.clone();
+        let mem_state = mempool_state.clone();
+        move || run_cleanup(config.clone(), db.clone(), mem_state.clone())
     });
 
-    let tx_processor = tokio::spawn({
+    let tx_processor = supervisor.spawn_draining("Tx Processor", {
         let db = db.clone();
         let mem_state = mempool_state.clone();
-        let rx = tx_receiver;
-        async move {
-            while let Some(tx) = rx.recv().await{
-                mem_state.add_tx(tx.clone()).await;
+        move || {
+            let db = db.clone();
+            let mem_state = mem_state.clone();
+            let sanctions = sanctions.clone();
+            let rx = tx_receiver.clone();
+            async move {
+                let mut rx = rx.lock().await;
+                while let Some(mut tx) = rx.recv().await {
+                    tx.sanctions = sanctions.matches(&tx);
+                    let outcome = mem_state.add_tx(tx.clone()).await;
+                    if outcome == AddOutcome::Duplicate {
+                        continue;
+                    }
+
+                    if let AddOutcome::Added { replaced, replaced_by } = outcome {
+                        if let Err(e) = db.insert_transaction(&tx).await {
+                            eprintln!("Failed to store transaction {}: {:?}", tx.hash, e);
+                        }
 
-                let _ = db.insert_transaction(tx).await;
+                        // Link fee bumps and cancellations to their successor
+                        let link = match (replaced, replaced_by) {
+                            (Some(old), _) => Some((old, tx.hash.clone())),
+                            (_, Some(new)) => Some((tx.hash.clone(), new)),
+                            _ => None,
+                        };
+                        if let Some((old, new)) = link {
+                            if let Err(e) = db.mark_replaced(&old, &new).await {
+                                eprintln!("Failed to mark {} as replaced: {:?}", old, e);
+                            }
+                        }
+                    }
+
+                    if let Err(e) = db.insert_sighting(&tx).await {
+                        eprintln!("Failed to store sighting of {}: {:?}", tx.hash, e);
+                    }
+                }
+                Ok(())
             }
         }
     });
 
-    let block_processor = tokio::spawn({
+    let block_processor = supervisor.spawn_draining("Block Processor", {
+        let report_interval = config.report_interval_blocks;
+        let db = db.clone();
+        let mem_state = mempool_state.clone();
         let detector = detector.clone();
+        move || {
+            let db = db.clone();
+            let mem_state = mem_state.clone();
+            let detector = detector.clone();
+            let rx = block_receiver.clone();
+            async move {
+                let mut rx = rx.lock().await;
+                while let Some(event) = rx.recv().await {
+                    let block = match event {
+                        ChainEvent::Block(block) => *block,
+                        ChainEvent::Reorg(reorg) => {
+                            // Orphaned inclusions go back to pending, the new chain's blocks follow
+                            let rolled_back = mem_state.rollback_inclusions(reorg.common_ancestor).await;
+                            detector.rollback(reorg.common_ancestor).await;
+                            if let Err(e) = db.record_reorg(&reorg, &rolled_back).await {
+                                eprintln!("Failed to record reorg above #{}: {:?}", reorg.common_ancestor, e);
+                            }
+                            continue;
+                        }
+                    };
+
+                    // 1. Store the block
+                    if let Err(e) = db.insert_block(&block).await {
+                        eprintln!("Failed to store block #{}: {:?}", block.number, e);
+                    }
+
+                    // 2. Update state
+                    let inclusions = mem_state.mark_included_txs(&block).await;
+                    if let Err(e) = db.mark_included(&inclusions).await {
+                        eprintln!("Failed to record inclusions in block #{}: {:?}", block.number, e);
+                    }
+
+                    // 3. Run detection (events are persisted by the detector)
+                    let events = detector.scan_mempool(&block).await;
+                    for event in &events {
+                        let proposers: Vec<String> = event.skipped_by_proposers.iter().map(u64::to_string).collect();
+                        println!("[CENSORED?] {} | fee percentile {:.2} | {} blocks | seen by {} nodes | confidence {:.2} | skipped by {} | proposers {}",
+                            event.tx_hash,
+                            event.fee_percentile,
+                            event.blocks_pending,
+                            event.seen_by_nodes,
+                            event.confidence_score,
+                            event.skipped_by.join(", "),
+                            proposers.join(", ")
+                        );
+                    }
+
+                    if report_interval > 0 && block.number % report_interval == 0 {
+                        detector.report_by_tx_type().await;
+                        detector.report_by_builder().await;
+                        detector.report_by_sanctions().await;
+                    }
+                }
+                Ok(())
+            }
+        }
+    });
+
+    let gap_processor = supervisor.spawn_draining("Gap Processor", {
+        let db = db.clone();
         let mem_state = mempool_state.clone();
+        move || {
+            let db = db.clone();
+            let mem_state = mem_state.clone();
+            let rx = gap_receiver.clone();
+            async move {
+                let mut rx = rx.lock().await;
+                while let Some(gap) = rx.recv().await {
+                    if let Err(e) = db.insert_visibility_gap(&gap).await {
+                        eprintln!("Failed to store {} visibility gap: {:?}", gap.source, e);
+                    }
+                    mem_state.record_gap(gap).await;
+                }
+                Ok(())
+            }
+        }
+    });
+
+    let pool_processor = supervisor.spawn_draining("Pool Processor", {
         let db = db.clone();
-        let mut rx = block_receiver;
+        let mem_state = mempool_state.clone();
+        move || {
+            let db = db.clone();
+            let mem_state = mem_state.clone();
+            let rx = pool_receiver.clone();
+            async move {
+                let mut rx = rx.lock().await;
+                while let Some(view) = rx.recv().await {
+                    for (hash, status) in mem_state.reconcile(&view).await {
+                        if let Err(e) = db.update_tx_status(&hash, status.as_str(), None).await {
+                            eprintln!("Failed to update status of {}: {:?}", hash, e);
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    });
 
-        async move {
-            while let Some(block) = rx.recv().await{
-                // 1. New block arrived
-                println("New block recieved: #{:?}", block.number);
-                let _ = db.insert_block(&block).await;
+    wait_for_shutdown_signal().await?;
+    println!("🛑 Shutdown requested, draining pipeline...");
+    let _ = shutdown_sender.send(true);
 
-                // 2. Update state
-                mem_state.mark_included_txs(&block.tx_hashes).await;
-            }
+    // Producers stop first, which drops their senders and lets the processors drain and exit
+    for handle in mempool_handles {
+        let _ = handle.await;
+    }
+    let _ = tokio::join!(block_handle, cleanup_handle);
+    if let Some(handle) = txpool_handle {
+        let _ = handle.await;
+    }
+    let _ = tokio::join!(tx_processor, block_processor, gap_processor, pool_processor);
 
-            // 3. RUN DETECTION
-            let events = detector.scan_mempool(block.number).await;
+    db.flush().await?;
+    println!("👋 CenCar stopped cleanly");
 
-            let snapshoot = self.mem_state.calculate_snapshoot().await;
+    Ok(())
+}
 
-            let pending_txs = self.mempool_state.get_pending_txs().await;
+async fn run_cleanup(
+    config: Config,
+    db: Arc<Repository>,
+    mem_state: Arc<MempoolState>,
+) -> Result<()> {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.cleanup_interval_secs));
+    // First tick fires immediately, nothing to clean yet
+    interval.tick().await;
 
-            
-            
-            // 4. Store results
+    loop {
+        interval.tick().await;
 
+        for hash in mem_state.cleanup_old_txs(config.mempool_max_age_secs).await {
+            if let Err(e) = db.update_tx_status(&hash, TxStatus::Dropped.as_str(), None).await {
+                eprintln!("Failed to update status of {}: {:?}", hash, e);
+            }
         }
+        db.cleanup_old_data(config.db_retention_days).await?;
 
-    });
-     
-    tokio::try_join!(
-          mempool_handle,
-          block_handle,
-          tx_processor,
-          block_processor,
-          cleanup_handle,
-      )?;
+        println!("🧹 Cleanup done | {} txs tracked", mem_state.get_tx_count().await);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_shutdown_signal() -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
 
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res?,
+        _ = sigterm.recv() => {}
+    }
     Ok(())
+}
 
+#[cfg(not(unix))]
+async fn wait_for_shutdown_signal() -> Result<()> {
+    tokio::signal::ctrl_c().await?;
+    Ok(())
 }
@@ -0,0 +1,182 @@
+use std::time::Duration;
+use eyre::{Result, eyre};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+use crate::types::{MinedBlock, SLOT_TIME_SECS};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Slot and validator that proposed a block
+#[derive(Debug, Clone, Copy)]
+pub struct Proposer {
+    pub slot: u64,
+    pub proposer_index: u64,
+}
+
+// Beacon API responses wrap everything in `data` and encode numbers as strings
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct Genesis {
+    genesis_time: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderData {
+    header: SignedHeader,
+}
+
+#[derive(Debug, Deserialize)]
+struct SignedHeader {
+    message: HeaderMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeaderMessage {
+    slot: String,
+    proposer_index: String,
+}
+
+// Minimal beacon node API client, only what's needed to find who proposed a block
+pub struct BeaconClient {
+    client: reqwest::Client,
+    url: String,
+    genesis_time: OnceCell<u64>,
+}
+
+impl BeaconClient {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            url: url.trim_end_matches('/').to_string(),
+            genesis_time: OnceCell::new(),
+        })
+    }
+
+    // Proposer of the beacon block carrying `block`, None if the node doesn't have it
+    pub async fn proposer(&self, block: &MinedBlock) -> Result<Option<Proposer>> {
+        let genesis_time = *self.genesis_time.get_or_try_init(|| self.fetch_genesis_time()).await?;
+        if block.timestamp < genesis_time {
+            return Ok(None);
+        }
+        // Payload timestamps are the start of their slot
+        let slot = (block.timestamp - genesis_time) / SLOT_TIME_SECS;
+
+        let response = self.client
+            .get(format!("{}/eth/v1/beacon/headers/{}", self.url, slot))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let header: Response<HeaderData> = response.error_for_status()?.json().await?;
+
+        let message = header.data.header.message;
+        Ok(Some(Proposer {
+            slot: parse_number(&message.slot)?,
+            proposer_index: parse_number(&message.proposer_index)?,
+        }))
+    }
+
+    async fn fetch_genesis_time(&self) -> Result<u64> {
+        let genesis: Response<Genesis> = self.client
+            .get(format!("{}/eth/v1/beacon/genesis", self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        parse_number(&genesis.data.genesis_time)
+    }
+}
+
+fn parse_number(value: &str) -> Result<u64> {
+    value.parse().map_err(|_| eyre!("Beacon API returned an invalid number: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const GENESIS_TIME: u64 = 1_606_824_023;
+
+    // Serves the two beacon endpoints the client uses, anything else is a 404
+    async fn stub_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+                let body = match path {
+                    "/eth/v1/beacon/genesis" => Some(format!(r#"{{"data":{{"genesis_time":"{}"}}}}"#, GENESIS_TIME)),
+                    "/eth/v1/beacon/headers/100" => {
+                        Some(r#"{"data":{"header":{"message":{"slot":"100","proposer_index":"4242"}}}}"#.to_string())
+                    }
+                    "/eth/v1/beacon/headers/102" => {
+                        Some(r#"{"data":{"header":{"message":{"slot":"102","proposer_index":"0x10"}}}}"#.to_string())
+                    }
+                    _ => None,
+                };
+                let response = match body {
+                    Some(body) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn block_at(timestamp: u64) -> MinedBlock {
+        MinedBlock { timestamp, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn proposer_of_block_slot() {
+        let client = BeaconClient::new(&format!("{}/", stub_server().await)).unwrap();
+
+        let proposer = client.proposer(&block_at(GENESIS_TIME + 100 * SLOT_TIME_SECS)).await.unwrap().unwrap();
+        assert_eq!(proposer.slot, 100);
+        assert_eq!(proposer.proposer_index, 4242);
+    }
+
+    #[tokio::test]
+    async fn missing_slot_has_no_proposer() {
+        let client = BeaconClient::new(&stub_server().await).unwrap();
+
+        assert!(client.proposer(&block_at(GENESIS_TIME + 101 * SLOT_TIME_SECS)).await.unwrap().is_none());
+        assert!(client.proposer(&block_at(GENESIS_TIME - 1)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn numbers_must_be_decimal_strings() {
+        let client = BeaconClient::new(&stub_server().await).unwrap();
+
+        let error = client.proposer(&block_at(GENESIS_TIME + 102 * SLOT_TIME_SECS)).await.unwrap_err();
+        assert!(error.to_string().contains("invalid number: 0x10"));
+    }
+}
@@ -1,68 +1,305 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
 use alloy::{
-    providers::{Provider, ProviderBuilder, WsConnect},
-    primitives::U256,
+    consensus::Transaction as _,
+    providers::Provider,
+    primitives::{B256, U256},
     rpc::types::BlockTransactionsKind,
 };
 use eyre::Result;
 use futures_util::StreamExt;
 use tokio::sync::mpsc;
-use crate::config::Config;
-use crate::types::MinedBlock;
+use crate::config::{BlobParams, Config, ThresholdSource};
+use crate::network::beacon::BeaconClient;
+use crate::network::reconnect::{connect_source, Backoff};
+use crate::network::relay::RelaySource;
+use crate::network::source::NodeProvider;
+use crate::types::{current_timestamp, ChainEvent, MinedBlock, Reorg, VisibilityGap};
+
+// How many forwarded blocks we remember to detect and unwind reorgs
+const REORG_HISTORY: usize = 64;
 
 pub struct BlockMonitor {
     config: Config,
+    // Last block number forwarded downstream, used to backfill after a reconnect
+    last_block: Option<u64>,
+    // Most recently forwarded blocks, oldest first
+    recent: VecDeque<MinedBlock>,
+    relays: Option<Arc<dyn RelaySource>>,
+    beacon: Option<Arc<BeaconClient>>,
 }
 
 impl BlockMonitor {
-    pub fn new(config: Config) -> Self {
-        Self { config }
+    pub fn new(config: Config, relays: Option<Arc<dyn RelaySource>>, beacon: Option<Arc<BeaconClient>>) -> Self {
+        Self { config, last_block: None, recent: VecDeque::new(), relays, beacon }
     }
 
     pub async fn spawn_monitor(
-        self,
-        tx_sender: mpsc::Sender<MinedBlock>,
+        mut self,
+        tx_sender: mpsc::Sender<ChainEvent>,
+        gap_sender: mpsc::Sender<VisibilityGap>,
     ) -> Result<()> {
-        println!("= Connecting to WebSocket for block monitoring: {}", self.config.rpc_url);
+        let mut backoff = Backoff::new();
+        let mut disconnected_at: Option<i64> = None;
 
-        let ws = WsConnect::new(self.config.rpc_url);
-        let provider = ProviderBuilder::new().on_ws(ws).await?;
+        loop {
+            let node = self.config.primary_node();
+            println!("= Connecting to {:?} source for block monitoring: {}", node.transport, node.url);
 
-        let sub = provider.subscribe_blocks().await?;
-        let mut stream = sub.into_stream();
+            let source = connect_source(node, &self.config, "Block Monitor", &mut backoff).await;
+            let provider = source.provider().clone();
 
-        println!("=@ Block Monitor Active. Waiting for new blocks...");
+            let mut stream = match source.new_blocks().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    eprintln!("⚠️  Block subscription failed: {} (retrying in {:?})", e, delay);
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+            backoff.reset();
 
-        while let Some(block_header) = stream.next().await {
-            let block_number = block_header.inner.number;
+            if let Some(started_at) = disconnected_at.take() {
+                // Catch up on everything mined while we were away
+                match provider.get_block_number().await {
+                    Ok(head) => {
+                        let from = self.last_block.map(|n| n + 1).unwrap_or(head);
+                        println!("⏪ Backfilling blocks #{}..=#{}", from, head);
+                        match self.forward_up_to(&provider, head, &tx_sender).await {
+                            Ok(true) => {}
+                            Ok(false) => return Ok(()),
+                            Err(e) => eprintln!("⚠️  Backfill failed: {}", e),
+                        }
+                    }
+                    Err(e) => eprintln!("⚠️  Could not fetch head for backfill: {}", e),
+                }
 
-            // Fetch full block to get transaction hashes
-            let tx_hashes = if let Ok(Some(full_block)) = provider.get_block_by_number(block_number.into(), BlockTransactionsKind::Hashes).await {
-                full_block.transactions.hashes().map(|h| h.to_string()).collect()
-            } else {
-                Vec::new()
-            };
+                let gap = VisibilityGap {
+                    source: "blocks".to_string(),
+                    node: self.config.primary_node().label.clone(),
+                    started_at,
+                    ended_at: current_timestamp(),
+                };
+                let _ = gap_sender.send(gap).await;
+            }
+
+            println!("=@ Block Monitor Active. Waiting for new blocks...");
+
+            while let Some(block_number) = stream.next().await {
+                match self.forward_up_to(&provider, block_number, &tx_sender).await {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(()),
+                    Err(e) => {
+                        eprintln!("⚠️  Failed to fetch block #{}: {}", block_number, e);
+                        break;
+                    }
+                }
+            }
+
+            eprintln!("⚠️  Block subscription ended, reconnecting...");
+            disconnected_at = Some(current_timestamp());
+        }
+    }
+
+    // Fetch and forward every block after `last_block` up to and including `target`,
+    // unwinding first if the chain we followed was replaced.
+    // Returns Ok(false) once the receiving side has gone away.
+    async fn forward_up_to(
+        &mut self,
+        provider: &NodeProvider,
+        target: u64,
+        tx_sender: &mpsc::Sender<ChainEvent>,
+    ) -> Result<bool> {
+        // A head we already forwarded means a same-height (or shorter) chain took over
+        if self.last_block.is_some_and(|last| target <= last) && !self.is_canonical(provider, target).await? {
+            match self.unwind(provider, tx_sender).await? {
+                None => return Ok(false),
+                Some(0) => return Ok(true),
+                Some(_) => {}
+            }
+        }
 
-            let mined_block = MinedBlock {
-                number: block_number,
-                timestamp: block_header.inner.timestamp,
-                base_fee: U256::from(block_header.inner.base_fee_per_gas.unwrap_or_default()),
-                tx_hashes,
-                gas_used: block_header.inner.gas_used as u128,
-                gas_limit: block_header.inner.gas_limit as u128,
+        let mut number = self.last_block.map(|n| n + 1).unwrap_or(target);
+        while number <= target {
+            let with_tips = self.config.threshold_source == ThresholdSource::Inclusion;
+            let Some(mut mined_block) = fetch_block(provider, number, &self.config.blob_params, with_tips).await? else {
+                // Node doesn't have it yet, the next header will pick it up
+                break;
             };
 
-            println!("[NEW BLOCK] #{} | {} txs | Base Fee: {} gwei",
+            let extends_ours = self.recent.back()
+                .is_none_or(|prev| prev.number + 1 != number || prev.hash == mined_block.parent_hash);
+            if !extends_ours {
+                match self.unwind(provider, tx_sender).await? {
+                    None => return Ok(false),
+                    // Node is still switching chains, retry on the next header
+                    Some(0) => break,
+                    Some(_) => {
+                        number = self.last_block.map(|n| n + 1).unwrap_or(number);
+                        continue;
+                    }
+                }
+            }
+
+            self.attribute_relay(&mut mined_block).await;
+            self.attribute_proposer(&mut mined_block).await;
+
+            println!("[NEW BLOCK] #{} | {} txs | Base Fee: {} gwei | Builder: {}{}{}",
                 mined_block.number,
                 mined_block.tx_hashes.len(),
-                mined_block.base_fee / U256::from(1_000_000_000u64)
+                mined_block.base_fee / U256::from(1_000_000_000u64),
+                mined_block.builder(),
+                mined_block.relay.as_ref().map(|relay| format!(" via {}", relay)).unwrap_or_default(),
+                mined_block.proposer_index.map(|index| format!(" | Proposer: {}", index)).unwrap_or_default()
             );
 
-            if let Err(e) = tx_sender.send(mined_block).await {
+            self.recent.push_back(mined_block.clone());
+            while self.recent.len() > REORG_HISTORY {
+                self.recent.pop_front();
+            }
+
+            if let Err(e) = tx_sender.send(ChainEvent::Block(Box::new(mined_block))).await {
                 eprintln!("Failed to send block to channel: {:?}", e);
+                return Ok(false);
+            }
+            self.last_block = Some(number);
+            number += 1;
+        }
+
+        Ok(true)
+    }
+
+    // Look up which relay delivered the block, if any. Attribution is best effort,
+    // a relay being unreachable never holds the block back.
+    async fn attribute_relay(&self, block: &mut MinedBlock) {
+        let Some(relays) = &self.relays else {
+            return;
+        };
+
+        match relays.delivery(block).await {
+            Ok(Some(delivery)) => {
+                block.relay = Some(delivery.relay);
+                block.builder_pubkey = Some(delivery.builder_pubkey);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("⚠️  Relay lookup failed for block #{}: {}", block.number, e),
+        }
+    }
+
+    // Same for the beacon slot and proposer
+    async fn attribute_proposer(&self, block: &mut MinedBlock) {
+        let Some(beacon) = &self.beacon else {
+            return;
+        };
+
+        match beacon.proposer(block).await {
+            Ok(Some(proposer)) => {
+                block.slot = Some(proposer.slot);
+                block.proposer_index = Some(proposer.proposer_index);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("⚠️  Proposer lookup failed for block #{}: {}", block.number, e),
+        }
+    }
+
+    // Whether our copy of block `number` is still on the node's canonical chain
+    async fn is_canonical(&self, provider: &NodeProvider, number: u64) -> Result<bool> {
+        let Some(ours) = self.recent.iter().find(|block| block.number == number) else {
+            return Ok(true);
+        };
+        Ok(block_hash_at(provider, number).await? == Some(ours.hash))
+    }
+
+    // Drop our blocks that are no longer canonical and report them downstream.
+    // Returns the reorg depth, or None once the receiving side has gone away.
+    async fn unwind(&mut self, provider: &NodeProvider, tx_sender: &mpsc::Sender<ChainEvent>) -> Result<Option<u64>> {
+        let mut orphaned = Vec::new();
+        while let Some(block) = self.recent.back() {
+            if block_hash_at(provider, block.number).await? == Some(block.hash) {
                 break;
             }
+            orphaned.extend(self.recent.pop_back());
         }
+        if orphaned.is_empty() {
+            return Ok(Some(0));
+        }
+        orphaned.reverse();
+
+        // Deeper than our history: everything we remember is orphaned, older blocks are assumed fine
+        let common_ancestor = match self.recent.back() {
+            Some(block) => block.number,
+            None => orphaned[0].number.saturating_sub(1),
+        };
+        let depth = orphaned.len() as u64;
+        println!("🔀 Reorg detected: {} block(s) above #{} replaced", depth, common_ancestor);
+
+        let reorg = Reorg {
+            common_ancestor,
+            orphaned,
+            detected_at: current_timestamp(),
+        };
+        if let Err(e) = tx_sender.send(ChainEvent::Reorg(reorg)).await {
+            eprintln!("Failed to send reorg to channel: {:?}", e);
+            return Ok(None);
+        }
+        self.last_block = Some(common_ancestor);
 
-        Ok(())
+        Ok(Some(depth))
     }
 }
+
+async fn block_hash_at(provider: &NodeProvider, number: u64) -> Result<Option<B256>> {
+    let block = provider
+        .get_block_by_number(number.into(), BlockTransactionsKind::Hashes)
+        .await?;
+    Ok(block.map(|block| block.header.hash))
+}
+
+// With `with_tips` the tx bodies are fetched too, to record what the builder actually accepted
+async fn fetch_block(
+    provider: &NodeProvider,
+    number: u64,
+    blob_params: &BlobParams,
+    with_tips: bool,
+) -> Result<Option<MinedBlock>> {
+    let kind = if with_tips { BlockTransactionsKind::Full } else { BlockTransactionsKind::Hashes };
+    let Some(full_block) = provider.get_block_by_number(number.into(), kind).await? else {
+        return Ok(None);
+    };
+
+    let header = &full_block.header.inner;
+    let excess_blob_gas = header.excess_blob_gas.unwrap_or_default();
+    let base_fee = header.base_fee_per_gas.unwrap_or_default();
+
+    // The builder's own payment tx pays no tip, leave it out. So do bundles paying the builder
+    // by coinbase transfer: their zero tips would drag the threshold down to nothing.
+    let mut included_tips: Vec<U256> = full_block.transactions.txns()
+        .filter(|tx| tx.from != header.beneficiary)
+        .filter_map(|tx| tx.inner.effective_tip_per_gas(base_fee))
+        .filter(|tip| *tip > 0)
+        .map(U256::from)
+        .collect();
+    included_tips.sort();
+
+    Ok(Some(MinedBlock {
+        number,
+        hash: full_block.header.hash,
+        parent_hash: header.parent_hash,
+        timestamp: header.timestamp,
+        base_fee: U256::from(base_fee),
+        tx_hashes: full_block.transactions.hashes().map(|h| h.to_string()).collect(),
+        gas_used: header.gas_used as u128,
+        gas_limit: header.gas_limit as u128,
+        blob_gas_used: header.blob_gas_used.unwrap_or_default(),
+        excess_blob_gas,
+        blob_base_fee: blob_params.base_fee(excess_blob_gas),
+        included_tips,
+        fee_recipient: header.beneficiary,
+        extra_data: header.extra_data.clone(),
+        relay: None,
+        builder_pubkey: None,
+        slot: None,
+        proposer_index: None,
+    }))
+}
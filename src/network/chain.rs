@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{Address, U256, U64},
+    providers::Provider,
+    rpc::{client::BatchRequest, json_rpc::RpcReturn},
+};
+use eyre::Result;
+use tokio::sync::Mutex;
+use crate::config::Config;
+use crate::network::source::{self, NodeProvider};
+
+// On-chain account state lookups for the primary node, batched and cached per block
+pub struct ChainReader {
+    config: Config,
+    provider: Mutex<Option<NodeProvider>>,
+    nonce_cache: Mutex<BlockCache<u64>>,
+    balance_cache: Mutex<BlockCache<U256>>,
+}
+
+// Values fetched at a single block; anything cached for an older block is discarded
+struct BlockCache<T> {
+    block: u64,
+    values: HashMap<Address, T>,
+}
+
+impl<T> BlockCache<T> {
+    fn new() -> Self {
+        Self { block: 0, values: HashMap::new() }
+    }
+
+    fn at(&mut self, block: u64) -> &mut HashMap<Address, T> {
+        if self.block != block {
+            self.block = block;
+            self.values.clear();
+        }
+        &mut self.values
+    }
+}
+
+impl ChainReader {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            provider: Mutex::new(None),
+            nonce_cache: Mutex::new(BlockCache::new()),
+            balance_cache: Mutex::new(BlockCache::new()),
+        }
+    }
+
+    // Account nonces (eth_getTransactionCount) of `senders` as of `block`
+    pub async fn nonces(&self, block: u64, senders: &[Address]) -> Result<HashMap<Address, u64>> {
+        let mut cache = self.nonce_cache.lock().await;
+        let cached = cache.at(block);
+
+        let missing: Vec<Address> = unique(senders)
+            .into_iter()
+            .filter(|sender| !cached.contains_key(sender))
+            .collect();
+
+        if !missing.is_empty() {
+            let provider = self.provider().await?;
+            let fetched = self
+                .batch_by_sender::<U64>(&provider, "eth_getTransactionCount", block, &missing)
+                .await?;
+            cached.extend(fetched.into_iter().map(|(sender, nonce)| (sender, nonce.to::<u64>())));
+        }
+
+        Ok(senders.iter().filter_map(|s| cached.get(s).map(|n| (*s, *n))).collect())
+    }
+
+    // Account balances (eth_getBalance) of `senders` as of `block`
+    pub async fn balances(&self, block: u64, senders: &[Address]) -> Result<HashMap<Address, U256>> {
+        let mut cache = self.balance_cache.lock().await;
+        let cached = cache.at(block);
+
+        let missing: Vec<Address> = unique(senders)
+            .into_iter()
+            .filter(|sender| !cached.contains_key(sender))
+            .collect();
+
+        if !missing.is_empty() {
+            let provider = self.provider().await?;
+            let fetched = self
+                .batch_by_sender::<U256>(&provider, "eth_getBalance", block, &missing)
+                .await?;
+            cached.extend(fetched);
+        }
+
+        Ok(senders.iter().filter_map(|s| cached.get(s).map(|b| (*s, *b))).collect())
+    }
+
+    // One `method(address, block)` call per sender, sent as a single JSON-RPC batch
+    async fn batch_by_sender<R>(
+        &self,
+        provider: &NodeProvider,
+        method: &'static str,
+        block: u64,
+        senders: &[Address],
+    ) -> Result<Vec<(Address, R)>>
+    where
+        R: RpcReturn,
+    {
+        let result = async {
+            let tag = BlockNumberOrTag::Number(block);
+            let mut batch = BatchRequest::new(provider.client());
+            let waiters = senders
+                .iter()
+                .map(|sender| batch.add_call::<_, R>(method, &(sender, tag)))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            batch.send().await?;
+
+            let mut values = Vec::with_capacity(waiters.len());
+            for (sender, waiter) in senders.iter().zip(waiters) {
+                values.push((*sender, waiter.await?));
+            }
+            Ok::<_, eyre::Report>(values)
+        }
+        .await;
+
+        if result.is_err() {
+            self.reset().await;
+        }
+        result
+    }
+
+    // Assume the connection is bad and reconnect on the next call
+    pub async fn reset(&self) {
+        *self.provider.lock().await = None;
+    }
+
+    pub async fn provider(&self) -> Result<NodeProvider> {
+        let mut provider = self.provider.lock().await;
+        if let Some(provider) = provider.as_ref() {
+            return Ok(provider.clone());
+        }
+
+        let connected = source::connect(self.config.primary_node(), &self.config).await?.provider().clone();
+        *provider = Some(connected.clone());
+        Ok(connected)
+    }
+}
+
+fn unique(senders: &[Address]) -> Vec<Address> {
+    let mut senders = senders.to_vec();
+    senders.sort();
+    senders.dedup();
+    senders
+}
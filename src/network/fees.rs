@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use alloy::primitives::U256;
+use crate::types::{effective_tip, PendingTx};
+
+// Effective tips of the txs currently competing for inclusion, kept ordered so percentile
+// queries are O(log n). Tips depend on the base fee, so the order is rebuilt when it moves
+// (once per block); in between, inserts and removals are O(log n) as well.
+pub struct FeeDistribution {
+    base_fee: U256,
+    // (max_fee, max_priority_fee) of every tx in the distribution
+    caps: HashMap<String, (U256, U256)>,
+    tips: OrderedMultiset,
+}
+
+impl FeeDistribution {
+    pub fn new() -> Self {
+        Self {
+            base_fee: U256::ZERO,
+            caps: HashMap::new(),
+            tips: OrderedMultiset::new(),
+        }
+    }
+
+    pub fn insert(&mut self, tx: &PendingTx) {
+        if self.caps.contains_key(&tx.hash) {
+            return;
+        }
+        self.caps.insert(tx.hash.clone(), (tx.max_fee, tx.max_priority_fee));
+        self.tips.insert(tx.effective_tip(self.base_fee));
+    }
+
+    pub fn remove(&mut self, tx_hash: &str) {
+        if let Some((max_fee, max_priority_fee)) = self.caps.remove(tx_hash) {
+            self.tips.remove(effective_tip(max_fee, max_priority_fee, self.base_fee));
+        }
+    }
+
+    pub fn set_base_fee(&mut self, base_fee: U256) {
+        if base_fee == self.base_fee {
+            return;
+        }
+
+        self.base_fee = base_fee;
+        self.tips.clear();
+        for (max_fee, max_priority_fee) in self.caps.values() {
+            self.tips.insert(effective_tip(*max_fee, *max_priority_fee, base_fee));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.caps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.caps.is_empty()
+    }
+
+    // Tip at percentile `p` (0.0..=1.0), zero when nothing is pending
+    pub fn percentile(&self, p: f64) -> U256 {
+        let len = self.tips.len();
+        if len == 0 {
+            return U256::ZERO;
+        }
+
+        let index = ((len as f64 * p) as u64).min(len - 1);
+        self.tips.select(index).unwrap_or_default()
+    }
+
+    // Percentile rank of `tip` (0.0..=1.0): share of tips below it, counting ties as half
+    pub fn rank(&self, tip: U256) -> f64 {
+        let len = self.tips.len();
+        if len == 0 {
+            return 0.0;
+        }
+
+        let below = self.tips.count_below(tip);
+        let equal = self.tips.count_at_or_below(tip) - below;
+        (below as f64 + equal as f64 / 2.0) / len as f64
+    }
+}
+
+impl Default for FeeDistribution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: U256,
+    // Copies of `key`, many txs pay exactly the same tip
+    count: u64,
+    // Total count in this subtree
+    size: u64,
+    priority: u64,
+    left: usize,
+    right: usize,
+}
+
+// Treap with subtree sizes, stored in an arena so nodes are reused instead of reallocated
+struct OrderedMultiset {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: usize,
+    seed: u64,
+}
+
+impl OrderedMultiset {
+    fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NIL,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.size(self.root)
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.free.clear();
+        self.root = NIL;
+    }
+
+    fn insert(&mut self, key: U256) {
+        self.root = self.insert_at(self.root, key);
+    }
+
+    fn remove(&mut self, key: U256) -> bool {
+        let (root, removed) = self.remove_at(self.root, key);
+        self.root = root;
+        removed
+    }
+
+    fn count_below(&self, key: U256) -> u64 {
+        let mut t = self.root;
+        let mut count = 0;
+        while t != NIL {
+            let node = &self.nodes[t];
+            if key <= node.key {
+                t = node.left;
+            } else {
+                count += self.size(node.left) + node.count;
+                t = node.right;
+            }
+        }
+        count
+    }
+
+    fn count_at_or_below(&self, key: U256) -> u64 {
+        let mut t = self.root;
+        let mut count = 0;
+        while t != NIL {
+            let node = &self.nodes[t];
+            if key < node.key {
+                t = node.left;
+            } else {
+                count += self.size(node.left) + node.count;
+                t = node.right;
+            }
+        }
+        count
+    }
+
+    // k-th smallest value, 0-based
+    fn select(&self, mut k: u64) -> Option<U256> {
+        let mut t = self.root;
+        while t != NIL {
+            let node = &self.nodes[t];
+            let left = self.size(node.left);
+            if k < left {
+                t = node.left;
+            } else if k < left + node.count {
+                return Some(node.key);
+            } else {
+                k -= left + node.count;
+                t = node.right;
+            }
+        }
+        None
+    }
+
+    fn insert_at(&mut self, t: usize, key: U256) -> usize {
+        if t == NIL {
+            return self.alloc(key);
+        }
+
+        if key == self.nodes[t].key {
+            self.nodes[t].count += 1;
+        } else if key < self.nodes[t].key {
+            let left = self.insert_at(self.nodes[t].left, key);
+            self.nodes[t].left = left;
+            if self.nodes[left].priority > self.nodes[t].priority {
+                return self.rotate_right(t);
+            }
+        } else {
+            let right = self.insert_at(self.nodes[t].right, key);
+            self.nodes[t].right = right;
+            if self.nodes[right].priority > self.nodes[t].priority {
+                return self.rotate_left(t);
+            }
+        }
+
+        self.update(t);
+        t
+    }
+
+    fn remove_at(&mut self, t: usize, key: U256) -> (usize, bool) {
+        if t == NIL {
+            return (NIL, false);
+        }
+
+        let removed = if key == self.nodes[t].key {
+            if self.nodes[t].count == 1 {
+                let merged = self.merge(self.nodes[t].left, self.nodes[t].right);
+                self.free.push(t);
+                return (merged, true);
+            }
+            self.nodes[t].count -= 1;
+            true
+        } else if key < self.nodes[t].key {
+            let (left, removed) = self.remove_at(self.nodes[t].left, key);
+            self.nodes[t].left = left;
+            removed
+        } else {
+            let (right, removed) = self.remove_at(self.nodes[t].right, key);
+            self.nodes[t].right = right;
+            removed
+        };
+
+        self.update(t);
+        (t, removed)
+    }
+
+    // Join two treaps where every key in `a` is below every key in `b`
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.merge(self.nodes[a].right, b);
+            self.nodes[a].right = right;
+            self.update(a);
+            a
+        } else {
+            let left = self.merge(a, self.nodes[b].left);
+            self.nodes[b].left = left;
+            self.update(b);
+            b
+        }
+    }
+
+    fn rotate_right(&mut self, t: usize) -> usize {
+        let l = self.nodes[t].left;
+        self.nodes[t].left = self.nodes[l].right;
+        self.nodes[l].right = t;
+        self.update(t);
+        self.update(l);
+        l
+    }
+
+    fn rotate_left(&mut self, t: usize) -> usize {
+        let r = self.nodes[t].right;
+        self.nodes[t].right = self.nodes[r].left;
+        self.nodes[r].left = t;
+        self.update(t);
+        self.update(r);
+        r
+    }
+
+    fn update(&mut self, t: usize) {
+        let size = self.size(self.nodes[t].left) + self.nodes[t].count + self.size(self.nodes[t].right);
+        self.nodes[t].size = size;
+    }
+
+    fn size(&self, t: usize) -> u64 {
+        if t == NIL { 0 } else { self.nodes[t].size }
+    }
+
+    fn alloc(&mut self, key: U256) -> usize {
+        let node = Node {
+            key,
+            count: 1,
+            size: 1,
+            priority: self.next_priority(),
+            left: NIL,
+            right: NIL,
+        };
+
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    // xorshift64, heap order only needs to look random
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::Address;
+
+    // Deterministic xorshift so failures reproduce
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn assert_matches(set: &OrderedMultiset, expected: &[u64]) {
+        assert_eq!(set.len(), expected.len() as u64);
+        for (i, value) in expected.iter().enumerate() {
+            assert_eq!(set.select(i as u64), Some(U256::from(*value)));
+        }
+        assert_eq!(set.select(expected.len() as u64), None);
+
+        // Probe every stored key and the gaps around them
+        for probe in 0..=33u64 {
+            let key = U256::from(probe);
+            let below = expected.iter().filter(|v| **v < probe).count() as u64;
+            let at_or_below = expected.iter().filter(|v| **v <= probe).count() as u64;
+            assert_eq!(set.count_below(key), below, "count_below({})", probe);
+            assert_eq!(set.count_at_or_below(key), at_or_below, "count_at_or_below({})", probe);
+        }
+    }
+
+    #[test]
+    fn multiset_matches_sorted_vec() {
+        for seed in 1..=20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut set = OrderedMultiset::new();
+            let mut expected: Vec<u64> = Vec::new();
+
+            for _ in 0..500 {
+                // Small key range so duplicates are frequent
+                let key = rng.next() % 32;
+                if rng.next().is_multiple_of(3) {
+                    let present = expected.iter().position(|v| *v == key);
+                    assert_eq!(set.remove(U256::from(key)), present.is_some());
+                    if let Some(index) = present {
+                        expected.remove(index);
+                    }
+                } else {
+                    set.insert(U256::from(key));
+                    let index = expected.partition_point(|v| *v <= key);
+                    expected.insert(index, key);
+                }
+                assert_matches(&set, &expected);
+            }
+
+            // Drain completely, then reuse the freed nodes
+            while let Some(key) = expected.pop() {
+                assert!(set.remove(U256::from(key)));
+            }
+            assert_matches(&set, &expected);
+            set.insert(U256::from(7u64));
+            assert_matches(&set, &[7]);
+        }
+    }
+
+    fn pending_tx(hash: &str, max_fee: u64, max_priority_fee: u64) -> PendingTx {
+        PendingTx {
+            hash: hash.to_string(),
+            from: Address::ZERO,
+            to: None,
+            max_priority_fee: U256::from(max_priority_fee),
+            max_fee: U256::from(max_fee),
+            nonce: 0,
+            first_seen: 0,
+            gas_limit: 21_000,
+            value: U256::ZERO,
+            input_data_size: 0,
+            tx_type: 2,
+            max_fee_per_blob_gas: U256::ZERO,
+            blob_count: 0,
+            delegations: Vec::new(),
+            touched: Vec::new(),
+            sanctions: Vec::new(),
+            queued: false,
+            seen_by: "0:test".to_string(),
+        }
+    }
+
+    #[test]
+    fn set_base_fee_rebuilds_effective_tips() {
+        let mut fees = FeeDistribution::new();
+        fees.insert(&pending_tx("a", 100, 10));
+        fees.insert(&pending_tx("b", 50, 20));
+        fees.insert(&pending_tx("c", 30, 30));
+        // Duplicate insert is ignored
+        fees.insert(&pending_tx("a", 100, 10));
+        assert_eq!(fees.len(), 3);
+
+        // Base fee 0: tips are the priority fees
+        assert_eq!(fees.percentile(0.0), U256::from(10u64));
+        assert_eq!(fees.percentile(0.99), U256::from(30u64));
+
+        // Base fee 25: min(prio, max_fee - 25) = 10, 20, 5
+        fees.set_base_fee(U256::from(25u64));
+        assert_eq!(fees.percentile(0.0), U256::from(5u64));
+        assert_eq!(fees.percentile(0.5), U256::from(10u64));
+        assert_eq!(fees.percentile(0.99), U256::from(20u64));
+        assert_eq!(fees.rank(U256::from(10u64)), 0.5);
+
+        // Removal uses the tip at the current base fee
+        fees.remove("c");
+        assert_eq!(fees.len(), 2);
+        assert_eq!(fees.percentile(0.0), U256::from(10u64));
+
+        // Base fee above a cap: that tx's tip drops to zero
+        fees.set_base_fee(U256::from(60u64));
+        assert_eq!(fees.percentile(0.0), U256::ZERO);
+        assert_eq!(fees.percentile(0.99), U256::from(10u64));
+
+        fees.remove("a");
+        fees.remove("b");
+        assert!(fees.is_empty());
+        assert_eq!(fees.percentile(0.5), U256::ZERO);
+    }
+}
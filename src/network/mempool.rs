@@ -1,56 +1,237 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use alloy::{
-    consensus::Transaction,
-    primitives::U256,
-    providers::{Provider, ProviderBuilder, WsConnect},
+    consensus::Transaction as _,
+    primitives::{B256, U256},
+    providers::Provider,
+    rpc::{client::BatchRequest, types::Transaction},
 };
 use eyre::Result;
 use futures_util::StreamExt;
-use tokio::sync::mpsc;
-use crate::config::Config;
-use crate::types::PendingTx;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+use crate::config::{Config, NodeEndpoint};
+use crate::network::reconnect::{connect_source, Backoff};
+use crate::network::source::{Announcement, NodeProvider};
+use crate::types::{current_timestamp, PendingTx, VisibilityGap};
+
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+// Flush a partial batch of hashes after this long so quiet periods don't add latency
+const BATCH_FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Counters for how announced transactions were obtained
+#[derive(Default)]
+struct MonitorStats {
+    full: AtomicU64,
+    fetched: AtomicU64,
+    unresolved: AtomicU64,
+}
 
 pub async fn spawn_monitor(
     config: Config,
+    node: NodeEndpoint,
     tx_sender: mpsc::Sender<PendingTx>,
+    gap_sender: mpsc::Sender<VisibilityGap>,
 ) -> Result<()> {
-    println!("🔌 Connecting to WebSocket at: {}", config.rpc_url);
-
-    // 1. Establish the WebSocket connection
-    let ws = WsConnect::new(config.rpc_url);
-    let provider = ProviderBuilder::new().on_ws(ws).await?;
-
-    // 2. Subscribe to the 'newPendingTransactions' stream
-    let sub = provider.subscribe_pending_transactions().await?;
-    let mut stream = sub.into_stream();
-
-    println!("👀 Mempool Monitor Active. Waiting for transactions...");
-
-    while let Some(tx_hash) = stream.next().await {
-        if let Ok(Some(tx)) = provider.get_transaction_by_hash(tx_hash).await {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
-
-            let simple_tx = PendingTx {
-                hash: tx_hash.to_string(),
-                from: tx.from,
-                to: tx.inner.to(),
-                max_priority_fee: U256::from(tx.inner.max_priority_fee_per_gas().unwrap_or_default()),
-                max_fee: U256::from(tx.inner.max_fee_per_gas()),
-                nonce: tx.inner.nonce(),
-                gas_limit: tx.inner.gas_limit(),
-                value: tx.inner.value(),
-                input_data_size: tx.inner.input().len(),
-                first_seen: now,
+    let mut backoff = Backoff::new();
+    // Set once we lose a working subscription, cleared when the next one is up
+    let mut disconnected_at: Option<i64> = None;
+    let stats = Arc::new(MonitorStats::default());
+    let mut last_report = Instant::now();
+
+    let batch_size = config.fetch_batch_size.max(1);
+    // Each permit is one in-flight batch; waiting on a permit pauses reading the subscription
+    let fetch_permits = Arc::new(Semaphore::new(config.fetch_concurrency.max(1)));
+
+    loop {
+        println!("🔌 Connecting to {:?} source at: {}", node.transport, node.url);
+
+        // 1. Establish the connection
+        let source = connect_source(&node, &config, "Mempool Monitor", &mut backoff).await;
+        let provider = source.provider().clone();
+
+        // 2. Subscribe to (or poll) new pending transactions
+        let mut stream = match source.pending_transactions(config.full_pending_txs).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                eprintln!("⚠️  Mempool subscription failed: {} (retrying in {:?})", e, delay);
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        backoff.reset();
+
+        if let Some(started_at) = disconnected_at.take() {
+            let gap = VisibilityGap {
+                source: "mempool".to_string(),
+                node: node.label.clone(),
+                started_at,
+                ended_at: current_timestamp(),
             };
+            println!("🕳️  Mempool visibility gap of {}s on {}", gap.ended_at - gap.started_at, node.label);
+            let _ = gap_sender.send(gap).await;
+        }
+
+        println!("👀 Mempool Monitor Active on {}. Waiting for transactions...", node.label);
+
+        // Fetch workers are aborted with the set if the monitor is torn down
+        let mut fetchers = JoinSet::new();
+        let mut hashes = Vec::with_capacity(batch_size);
+        let mut flush = tokio::time::interval(BATCH_FLUSH_INTERVAL);
+
+        loop {
+            let announcement = tokio::select! {
+                next = stream.next() => match next {
+                    Some(announcement) => announcement,
+                    None => break,
+                },
+                _ = flush.tick() => {
+                    if !hashes.is_empty() {
+                        let batch = std::mem::replace(&mut hashes, Vec::with_capacity(batch_size));
+                        dispatch_batch(&mut fetchers, &fetch_permits, &provider, batch, &node.label, &tx_sender, &stats).await;
+                    }
+                    while fetchers.try_join_next().is_some() {}
+                    if tx_sender.is_closed() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            match announcement {
+                Announcement::Full(tx) => {
+                    stats.full.fetch_add(1, Ordering::Relaxed);
+                    if let Err(e) = tx_sender.send(to_pending_tx(&tx, current_timestamp(), &node.label)).await {
+                        eprintln!("Failed to send transaction to channel: {:?}", e);
+                        return Ok(());
+                    }
+                }
+                // Either we are in hash mode or the node ignored the full-tx flag
+                Announcement::Hash(tx_hash) => {
+                    hashes.push(tx_hash);
+                    if hashes.len() >= batch_size {
+                        let batch = std::mem::replace(&mut hashes, Vec::with_capacity(batch_size));
+                        dispatch_batch(&mut fetchers, &fetch_permits, &provider, batch, &node.label, &tx_sender, &stats).await;
+                    }
+                }
+                Announcement::Unknown => {
+                    stats.unresolved.fetch_add(1, Ordering::Relaxed);
+                }
+            }
 
-            if let Err(e) = tx_sender.send(simple_tx).await {
-                eprintln!("Failed to send transaction to channel: {:?}", e);
-                break;
+            if last_report.elapsed() >= STATS_INTERVAL {
+                println!("📊 Mempool Monitor {} | {} full | {} fetched | {} unresolved | {} fetches in flight",
+                    node.label,
+                    stats.full.load(Ordering::Relaxed),
+                    stats.fetched.load(Ordering::Relaxed),
+                    stats.unresolved.load(Ordering::Relaxed),
+                    fetchers.len()
+                );
+                last_report = Instant::now();
             }
         }
+
+        eprintln!("⚠️  Mempool subscription ended, reconnecting...");
+        disconnected_at = Some(current_timestamp());
     }
+}
+
+// Wait for a free fetch slot, then resolve the batch of hashes in the background
+async fn dispatch_batch(
+    fetchers: &mut JoinSet<()>,
+    fetch_permits: &Arc<Semaphore>,
+    provider: &NodeProvider,
+    hashes: Vec<B256>,
+    node_label: &str,
+    tx_sender: &mpsc::Sender<PendingTx>,
+    stats: &Arc<MonitorStats>,
+) {
+    let first_seen = current_timestamp();
+    let permit = fetch_permits.clone().acquire_owned().await.expect("fetch semaphore is never closed");
+    let provider = provider.clone();
+    let tx_sender = tx_sender.clone();
+    let stats = stats.clone();
+    let node_label = node_label.to_string();
+
+    fetchers.spawn(async move {
+        let _permit = permit;
+
+        let txs = match fetch_batch(&provider, &hashes).await {
+            Ok(txs) => txs,
+            Err(e) => {
+                eprintln!("⚠️  Batch fetch of {} transactions failed: {}", hashes.len(), e);
+                stats.unresolved.fetch_add(hashes.len() as u64, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        for tx in txs {
+            match tx {
+                Some(tx) => {
+                    stats.fetched.fetch_add(1, Ordering::Relaxed);
+                    // Blocks while the processor is behind, holding the permit and slowing intake
+                    if tx_sender.send(to_pending_tx(&tx, first_seen, &node_label)).await.is_err() {
+                        return;
+                    }
+                }
+                // Already mined, replaced or dropped by the time we asked
+                None => {
+                    stats.unresolved.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+}
+
+// Resolve many hashes with a single JSON-RPC batch
+async fn fetch_batch(provider: &NodeProvider, hashes: &[B256]) -> Result<Vec<Option<Transaction>>> {
+    let mut batch = BatchRequest::new(provider.client());
+    let waiters = hashes
+        .iter()
+        .map(|hash| batch.add_call::<_, Option<Transaction>>("eth_getTransactionByHash", &(hash,)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    batch.send().await?;
+
+    let mut txs = Vec::with_capacity(waiters.len());
+    for waiter in waiters {
+        txs.push(waiter.await.ok().flatten());
+    }
+    Ok(txs)
+}
 
-    Ok(())
+pub fn to_pending_tx(tx: &Transaction, first_seen: i64, seen_by: &str) -> PendingTx {
+    // Legacy and access-list txs only have a gas price, which acts as both caps:
+    // their effective tip works out to gas_price - base_fee
+    let max_fee = tx.inner.max_fee_per_gas();
+    let max_priority_fee = tx.inner.max_priority_fee_per_gas().unwrap_or(max_fee);
+
+    PendingTx {
+        hash: tx.inner.tx_hash().to_string(),
+        from: tx.from,
+        to: tx.inner.to(),
+        max_priority_fee: U256::from(max_priority_fee),
+        max_fee: U256::from(max_fee),
+        nonce: tx.inner.nonce(),
+        gas_limit: tx.inner.gas_limit(),
+        value: tx.inner.value(),
+        input_data_size: tx.inner.input().len(),
+        tx_type: tx.inner.tx_type() as u8,
+        max_fee_per_blob_gas: U256::from(tx.inner.max_fee_per_blob_gas().unwrap_or_default()),
+        blob_count: tx.inner.blob_versioned_hashes().map_or(0, |hashes| hashes.len()),
+        delegations: tx.inner
+            .authorization_list()
+            .map(|list| list.iter().map(|auth| auth.address).collect())
+            .unwrap_or_default(),
+        touched: tx.inner
+            .access_list()
+            .map(|list| list.iter().map(|item| item.address).collect())
+            .unwrap_or_default(),
+        sanctions: Vec::new(),
+        queued: false,
+        first_seen,
+        seen_by: seen_by.to_string(),
+    }
 }
@@ -1,3 +1,10 @@
 pub mod mempool;
 pub mod blocks;
 pub mod state;
+pub mod fees;
+pub mod reconnect;
+pub mod txpool;
+pub mod source;
+pub mod chain;
+pub mod relay;
+pub mod beacon;
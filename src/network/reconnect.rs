@@ -0,0 +1,65 @@
+use std::time::Duration;
+use crate::config::{Config, NodeEndpoint};
+use crate::network::source::{self, MempoolSource};
+
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Exponential backoff with equal jitter
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self { attempt: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let cap = INITIAL_DELAY
+            .saturating_mul(1u32 << self.attempt.min(16))
+            .min(MAX_DELAY);
+        self.attempt = self.attempt.saturating_add(1);
+
+        // Pick a delay in [cap / 2, cap] so reconnecting monitors don't hit the node in lockstep
+        let half = cap.as_millis() as u64 / 2;
+        Duration::from_millis(half + jitter_millis(half + 1))
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Connect to a node, retrying with backoff until it succeeds
+pub async fn connect_source(
+    node: &NodeEndpoint,
+    config: &Config,
+    label: &str,
+    backoff: &mut Backoff,
+) -> Box<dyn MempoolSource> {
+    loop {
+        match source::connect(node, config).await {
+            Ok(source) => return source,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                eprintln!("⚠️  {} could not connect to {}: {} (retrying in {:?})", label, node.label, e, delay);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+fn jitter_millis(bound: u64) -> u64 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos() as u64;
+    nanos % bound
+}
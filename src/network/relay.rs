@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use alloy::primitives::B256;
+use eyre::{Result, eyre};
+use futures_util::future::{join_all, BoxFuture};
+use serde::Deserialize;
+use crate::config::Config;
+use crate::types::MinedBlock;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Which MEV-Boost relay delivered a block, and for which builder
+#[derive(Debug, Clone)]
+pub struct RelayDelivery {
+    pub relay: String,
+    pub builder_pubkey: String,
+}
+
+// Where relay attribution comes from
+pub trait RelaySource: Send + Sync {
+    // None when no relay delivered the block (locally built, or a relay we don't query)
+    fn delivery(&self, block: &MinedBlock) -> BoxFuture<'_, Result<Option<RelayDelivery>>>;
+}
+
+// One entry of the relay data API's bidtraces, only the fields we use
+#[derive(Debug, Deserialize)]
+struct BidTrace {
+    block_hash: B256,
+    builder_pubkey: String,
+    // Only set in mock files, the API response doesn't name the relay
+    #[serde(default)]
+    relay: Option<String>,
+}
+
+// Relay data API (`/relay/v1/data/bidtraces/proposer_payload_delivered`) of each configured relay
+pub struct RelayApi {
+    client: reqwest::Client,
+    relays: Vec<String>,
+}
+
+impl RelayApi {
+    async fn query(&self, relay: &str, block: &MinedBlock) -> Result<Option<RelayDelivery>> {
+        let url = format!("{}/relay/v1/data/bidtraces/proposer_payload_delivered", relay.trim_end_matches('/'));
+        let traces: Vec<BidTrace> = self.client
+            .get(url)
+            .query(&[("block_number", block.number)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(traces.into_iter()
+            .find(|trace| trace.block_hash == block.hash)
+            .map(|trace| RelayDelivery {
+                relay: relay_label(relay),
+                builder_pubkey: trace.builder_pubkey,
+            }))
+    }
+}
+
+impl RelaySource for RelayApi {
+    fn delivery(&self, block: &MinedBlock) -> BoxFuture<'_, Result<Option<RelayDelivery>>> {
+        let block = block.clone();
+        Box::pin(async move {
+            let results = join_all(self.relays.iter().map(|relay| self.query(relay, &block))).await;
+
+            // A single relay being down shouldn't hide the ones that answered
+            let mut last_error = None;
+            for result in results {
+                match result {
+                    Ok(Some(delivery)) => return Ok(Some(delivery)),
+                    Ok(None) => {}
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            match last_error {
+                Some(e) if self.relays.len() == 1 => Err(e),
+                _ => Ok(None),
+            }
+        })
+    }
+}
+
+// Deliveries read from a local JSON file in the API's format, for testing and replays
+pub struct MockRelay {
+    deliveries: HashMap<B256, RelayDelivery>,
+}
+
+impl MockRelay {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| eyre!("Could not read relay mock file {}: {}", path, e))?;
+        let traces: Vec<BidTrace> = serde_json::from_str(&contents)
+            .map_err(|e| eyre!("Invalid relay mock file {}: {}", path, e))?;
+
+        let deliveries = traces.into_iter()
+            .map(|trace| {
+                let delivery = RelayDelivery {
+                    relay: trace.relay.unwrap_or_else(|| "mock".to_string()),
+                    builder_pubkey: trace.builder_pubkey,
+                };
+                (trace.block_hash, delivery)
+            })
+            .collect();
+        Ok(Self { deliveries })
+    }
+}
+
+impl RelaySource for MockRelay {
+    fn delivery(&self, block: &MinedBlock) -> BoxFuture<'_, Result<Option<RelayDelivery>>> {
+        let delivery = self.deliveries.get(&block.hash).cloned();
+        Box::pin(async move { Ok(delivery) })
+    }
+}
+
+// RELAY_MOCK_FILE takes precedence over RELAY_URLS, None if neither is set
+pub fn from_config(config: &Config) -> Result<Option<Arc<dyn RelaySource>>> {
+    if let Some(path) = &config.relay_mock_file {
+        return Ok(Some(Arc::new(MockRelay::from_file(path)?)));
+    }
+    if config.relay_urls.is_empty() {
+        return Ok(None);
+    }
+
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    Ok(Some(Arc::new(RelayApi { client, relays: config.relay_urls.clone() })))
+}
+
+// Relays are known by host name
+fn relay_label(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    // Relay URLs usually carry the relay's pubkey as user info
+    let host = rest.split('/').next().unwrap_or(rest);
+    host.rsplit('@').next().unwrap_or(host).to_string()
+}
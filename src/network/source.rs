@@ -0,0 +1,164 @@
+use std::time::Duration;
+use alloy::{
+    primitives::B256,
+    providers::{IpcConnect, Provider, ProviderBuilder, RootProvider, WsConnect},
+    pubsub::SubscriptionItem,
+    rpc::types::Transaction,
+    transports::BoxTransport,
+};
+use eyre::{Result, eyre};
+use futures_util::{future::BoxFuture, stream::BoxStream, StreamExt};
+use crate::config::{Config, NodeEndpoint, RpcTransport};
+
+// Provider type shared by every backend, whatever the underlying transport
+pub type NodeProvider = RootProvider<BoxTransport>;
+
+// What the node pushed for a new pending transaction
+pub enum Announcement {
+    Full(Box<Transaction>),
+    Hash(B256),
+    Unknown,
+}
+
+// A connection to a node that can feed the mempool and block monitors
+pub trait MempoolSource: Send + Sync {
+    fn provider(&self) -> &NodeProvider;
+
+    // Stream of new pending transactions. With `full` the source tries to get bodies
+    // directly and falls back to hashes if the node doesn't support it.
+    fn pending_transactions(&self, full: bool) -> BoxFuture<'_, Result<BoxStream<'static, Announcement>>>;
+
+    // Stream of new head block numbers
+    fn new_blocks(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>>;
+}
+
+// WebSocket and IPC: both push notifications over eth_subscribe
+pub struct PubSubSource {
+    provider: NodeProvider,
+}
+
+impl MempoolSource for PubSubSource {
+    fn provider(&self) -> &NodeProvider {
+        &self.provider
+    }
+
+    fn pending_transactions(&self, full: bool) -> BoxFuture<'_, Result<BoxStream<'static, Announcement>>> {
+        Box::pin(async move {
+            if full {
+                match self.provider.subscribe_full_pending_transactions().await {
+                    Ok(sub) => {
+                        println!("📦 Subscribed to full pending transactions");
+                        let stream = sub.into_any_stream().map(|item| match item {
+                            SubscriptionItem::Item(tx) => Announcement::Full(Box::new(tx)),
+                            SubscriptionItem::Other(raw) => raw
+                                .get()
+                                .trim_matches('"')
+                                .parse()
+                                .map(Announcement::Hash)
+                                .unwrap_or(Announcement::Unknown),
+                        });
+                        return Ok(stream.boxed());
+                    }
+                    Err(e) => eprintln!("⚠️  Full pending transactions not supported ({}), using hashes", e),
+                }
+            }
+
+            let sub = self.provider.subscribe_pending_transactions().await?;
+            Ok(sub.into_stream().map(Announcement::Hash).boxed())
+        })
+    }
+
+    fn new_blocks(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>> {
+        Box::pin(async move {
+            let sub = self.provider.subscribe_blocks().await?;
+            Ok(sub.into_stream().map(|header| header.inner.number).boxed())
+        })
+    }
+}
+
+// Plain HTTP: poll eth_newPendingTransactionFilter / eth_newBlockFilter with eth_getFilterChanges
+pub struct HttpPollingSource {
+    provider: NodeProvider,
+    poll_interval: Duration,
+}
+
+impl MempoolSource for HttpPollingSource {
+    fn provider(&self) -> &NodeProvider {
+        &self.provider
+    }
+
+    fn pending_transactions(&self, full: bool) -> BoxFuture<'_, Result<BoxStream<'static, Announcement>>> {
+        Box::pin(async move {
+            if full {
+                match self.provider.watch_full_pending_transactions().await {
+                    Ok(poller) => {
+                        println!("📦 Polling full pending transactions");
+                        let stream = poller
+                            .with_poll_interval(self.poll_interval)
+                            .into_stream()
+                            .flat_map(futures_util::stream::iter)
+                            .map(|tx| Announcement::Full(Box::new(tx)));
+                        return Ok(stream.boxed());
+                    }
+                    Err(e) => eprintln!("⚠️  Full pending transaction filter not supported ({}), using hashes", e),
+                }
+            }
+
+            let poller = self.provider.watch_pending_transactions().await?;
+            let stream = poller
+                .with_poll_interval(self.poll_interval)
+                .into_stream()
+                .flat_map(futures_util::stream::iter)
+                .map(Announcement::Hash);
+            Ok(stream.boxed())
+        })
+    }
+
+    fn new_blocks(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>> {
+        Box::pin(async move {
+            let poller = self.provider.watch_blocks().await?;
+            let provider = self.provider.clone();
+
+            // The filter only yields hashes; the head number is all the block monitor needs
+            let stream = poller
+                .with_poll_interval(self.poll_interval)
+                .into_stream()
+                .filter(|hashes| futures_util::future::ready(!hashes.is_empty()))
+                .then(move |_| {
+                    let provider = provider.clone();
+                    async move { provider.get_block_number().await.ok() }
+                })
+                .filter_map(futures_util::future::ready);
+            Ok(stream.boxed())
+        })
+    }
+}
+
+// Connect to a node with its configured transport
+pub async fn connect(node: &NodeEndpoint, config: &Config) -> Result<Box<dyn MempoolSource>> {
+    let url = node.url.as_str();
+
+    let source: Box<dyn MempoolSource> = match node.transport {
+        RpcTransport::Ws => {
+            let provider = ProviderBuilder::new().on_ws(WsConnect::new(url)).await?.boxed();
+            Box::new(PubSubSource { provider })
+        }
+        RpcTransport::Ipc => {
+            let provider = ProviderBuilder::new().on_ipc(IpcConnect::new(url.to_string())).await?.boxed();
+            Box::new(PubSubSource { provider })
+        }
+        RpcTransport::Http => {
+            let url = url.parse().map_err(|e| eyre!("invalid HTTP RPC URL {}: {}", url, e))?;
+            let provider = ProviderBuilder::new().on_http(url).boxed();
+            Box::new(HttpPollingSource {
+                provider,
+                poll_interval: Duration::from_millis(config.http_poll_interval_ms),
+            })
+        }
+    };
+
+    // HTTP providers are built lazily, make sure the node actually answers
+    source.provider().get_block_number().await?;
+
+    Ok(source)
+}
@@ -1,82 +1,259 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use alloy::primitives::U256;
-use crate::types::{PendingTx, TrackedTx, TxStatus, MempoolSnapshot, FeePercentiles};
+use alloy::primitives::{Address, U256};
+use crate::network::fees::FeeDistribution;
+use crate::types::{current_timestamp, ExclusionReason, Inclusion, MinedBlock, PendingTx, TrackedTx, TxStatus, MempoolSnapshot, FeePercentiles, VisibilityGap, TxPoolView};
+
+// What add_tx did with a reported transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddOutcome {
+    // Already reported by this node
+    Duplicate,
+    // Already tracked, but this node hadn't reported it yet
+    NewSighting,
+    // First time we see the tx. `replaced` is the hash it superseded for the same (sender, nonce),
+    // `replaced_by` is set instead if it arrived after a better-paying replacement.
+    Added { replaced: Option<String>, replaced_by: Option<String> },
+}
 
 pub struct MempoolState {
     tracked_txs: Arc<RwLock<HashMap<String, TrackedTx>>>,
-    fee_distribution: Arc<RwLock<Vec<U256>>>,
+    // Live tx hash for each (sender, nonce)
+    sender_nonces: Arc<RwLock<HashMap<(Address, u64), String>>>,
+    // Effective tips of the txs still competing for inclusion
+    fee_distribution: Arc<RwLock<FeeDistribution>>,
+    visibility_gaps: Arc<RwLock<Vec<VisibilityGap>>>,
+    // Latest block passed to mark_included_txs
+    head_block: Arc<RwLock<u64>>,
 }
 
 impl MempoolState {
     pub fn new() -> Self {
         Self {
             tracked_txs: Arc::new(RwLock::new(HashMap::new())),
-            fee_distribution: Arc::new(RwLock::new(Vec::new())),
+            sender_nonces: Arc::new(RwLock::new(HashMap::new())),
+            fee_distribution: Arc::new(RwLock::new(FeeDistribution::new())),
+            visibility_gaps: Arc::new(RwLock::new(Vec::new())),
+            head_block: Arc::new(RwLock::new(0)),
         }
     }
 
-    pub async fn add_tx(&self, tx: PendingTx) {
+    pub async fn add_tx(&self, tx: PendingTx) -> AddOutcome {
         let mut tracked = self.tracked_txs.write().await;
         let mut fees = self.fee_distribution.write().await;
+        let mut sender_nonces = self.sender_nonces.write().await;
+
+        // Several nodes can report the same tx, keep the earliest sighting per node
+        if let Some(existing) = tracked.get_mut(&tx.hash) {
+            if existing.sightings.contains_key(&tx.seen_by) {
+                return AddOutcome::Duplicate;
+            }
+            existing.sightings.insert(tx.seen_by.clone(), tx.first_seen);
+            existing.tx.first_seen = existing.tx.first_seen.min(tx.first_seen);
+            return AddOutcome::NewSighting;
+        }
+
+        let now = current_timestamp();
+        let key = (tx.from, tx.nonce);
+        let mut replaced = None;
+        let mut replaced_by = None;
+
+        // Another live tx with the same (sender, nonce): the better-paying one wins, as in the node's pool
+        let current = sender_nonces.get(&key)
+            .and_then(|hash| tracked.get_mut(hash))
+            .filter(|current| is_live(&current.status));
+        match current {
+            Some(current) if outbids(&tx, &current.tx) => {
+                current.status = TxStatus::Replaced { by: tx.hash.clone() };
+                current.last_checked = now;
+                fees.remove(&current.tx.hash);
+                replaced = Some(current.tx.hash.clone());
+                sender_nonces.insert(key, tx.hash.clone());
+            }
+            Some(current) => replaced_by = Some(current.tx.hash.clone()),
+            None => {
+                sender_nonces.insert(key, tx.hash.clone());
+            }
+        }
+
+        let status = match &replaced_by {
+            Some(by) => TxStatus::Replaced { by: by.clone() },
+            None if tx.queued => TxStatus::Queued,
+            None => TxStatus::Pending,
+        };
 
         let tracked_tx = TrackedTx {
             tx: tx.clone(),
-            status: TxStatus::Pending,
-            last_checked: current_timestamp(),
+            status,
+            last_checked: now,
+            sightings: HashMap::from([(tx.seen_by.clone(), tx.first_seen)]),
+            exclusion: None,
+            first_seen_block: *self.head_block.read().await,
         };
 
+        sync_fee(&mut fees, &tracked_tx);
         tracked.insert(tx.hash.clone(), tracked_tx);
-        fees.push(tx.max_priority_fee);
+        AddOutcome::Added { replaced, replaced_by }
+    }
+
+    // Mark the tracked txs of a new block as included and return how long each one waited
+    pub async fn mark_included_txs(&self, block: &MinedBlock) -> Vec<Inclusion> {
+        let mut tracked = self.tracked_txs.write().await;
+        let mut fees = self.fee_distribution.write().await;
+        *self.head_block.write().await = block.number;
+
+        let mut inclusions = Vec::new();
+        for hash in &block.tx_hashes {
+            let Some(tracked_tx) = tracked.get_mut(hash) else {
+                continue;
+            };
+            if matches!(tracked_tx.status, TxStatus::Included { .. }) {
+                continue;
+            }
+
+            tracked_tx.status = TxStatus::Included { block_number: block.number };
+            tracked_tx.last_checked = current_timestamp();
+            fees.remove(hash);
+
+            inclusions.push(Inclusion {
+                tx_hash: hash.clone(),
+                block_number: block.number,
+                included_at: block.timestamp as i64,
+                latency_blocks: (tracked_tx.first_seen_block > 0)
+                    .then(|| block.number.saturating_sub(tracked_tx.first_seen_block)),
+                latency_secs: block.timestamp as i64 - tracked_tx.tx.first_seen,
+            });
+        }
+
+        inclusions
     }
 
-    pub async fn mark_included_txs(&self, tx_hashes: &[String]) {
+    // Undo inclusions in blocks above `common_ancestor` after a reorg. Returns the txs put back to pending.
+    pub async fn rollback_inclusions(&self, common_ancestor: u64) -> Vec<String> {
         let mut tracked = self.tracked_txs.write().await;
+        let mut fees = self.fee_distribution.write().await;
+        *self.head_block.write().await = common_ancestor;
 
-        for hash in tx_hashes {
-            if let Some(tracked_tx) = tracked.get_mut(hash) {
-                if let TxStatus::Pending = tracked_tx.status {
-                    tracked_tx.status = TxStatus::Included {
-                        block_number: 0, // Will be updated by detector
-                    };
+        let mut rolled_back = Vec::new();
+        for (hash, tracked_tx) in tracked.iter_mut() {
+            if let TxStatus::Included { block_number } = tracked_tx.status {
+                if block_number > common_ancestor {
+                    tracked_tx.status = TxStatus::Pending;
                     tracked_tx.last_checked = current_timestamp();
+                    sync_fee(&mut fees, tracked_tx);
+                    rolled_back.push(hash.clone());
                 }
             }
         }
+
+        rolled_back
     }
 
-    pub async fn calculate_snapshot(&self) -> MempoolSnapshot {
-        let fees = self.fee_distribution.read().await;
-        let tracked = self.tracked_txs.read().await;
+    // Move a tracked tx to a new status, returns false if it isn't tracked
+    pub async fn set_status(&self, tx_hash: &str, status: TxStatus) -> bool {
+        let mut tracked = self.tracked_txs.write().await;
+
+        match tracked.get_mut(tx_hash) {
+            Some(tracked_tx) => {
+                tracked_tx.status = status;
+                tracked_tx.last_checked = current_timestamp();
+                sync_fee(&mut *self.fee_distribution.write().await, tracked_tx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Tag (or clear) why a tx is excluded from analysis. Returns true if the tag changed.
+    pub async fn set_exclusion(&self, tx_hash: &str, reason: Option<ExclusionReason>) -> bool {
+        let mut tracked = self.tracked_txs.write().await;
+
+        match tracked.get_mut(tx_hash) {
+            Some(tracked_tx) if tracked_tx.exclusion != reason => {
+                tracked_tx.exclusion = reason;
+                true
+            }
+            _ => false,
+        }
+    }
 
-        let mut sorted_fees: Vec<U256> = fees.clone();
-        sorted_fees.sort();
+    pub async fn mark_censored(&self, tx_hash: &str) {
+        let mut tracked = self.tracked_txs.write().await;
 
-        let percentiles = if sorted_fees.is_empty() {
-            FeePercentiles {
-                p25: U256::ZERO,
-                p50: U256::ZERO,
-                p75: U256::ZERO,
-                p90: U256::ZERO,
+        if let Some(tracked_tx) = tracked.get_mut(tx_hash) {
+            if let TxStatus::Pending = tracked_tx.status {
+                tracked_tx.status = TxStatus::PotentiallyCensored;
+                tracked_tx.last_checked = current_timestamp();
             }
-        } else {
-            let len = sorted_fees.len();
-            FeePercentiles {
-                p25: sorted_fees[len * 25 / 100],
-                p50: sorted_fees[len * 50 / 100],
-                p75: sorted_fees[len * 75 / 100],
-                p90: sorted_fees[len * 90 / 100],
+        }
+    }
+
+    // Align tracked statuses with the node's txpool. Returns the txs whose status changed.
+    // Only txs that node reported can be dropped, others may live in other nodes' pools.
+    pub async fn reconcile(&self, view: &TxPoolView) -> Vec<(String, TxStatus)> {
+        let mut tracked = self.tracked_txs.write().await;
+        let mut fees = self.fee_distribution.write().await;
+        let now = current_timestamp();
+        let mut changed = Vec::new();
+
+        for (hash, tracked_tx) in tracked.iter_mut() {
+            let key = (tracked_tx.tx.from, tracked_tx.tx.nonce);
+
+            let new_status = match tracked_tx.status {
+                TxStatus::Queued if view.pending.contains(&key) => TxStatus::Pending,
+                TxStatus::Pending if view.queued.contains(&key) => TxStatus::Queued,
+                // Seen before the view was taken but no longer in the pool
+                TxStatus::Pending | TxStatus::Queued | TxStatus::PotentiallyCensored
+                    if tracked_tx.sightings.contains_key(&view.node)
+                        && tracked_tx.tx.first_seen < view.taken_at
+                        && !view.pending.contains(&key)
+                        && !view.queued.contains(&key) => TxStatus::Dropped,
+                _ => continue,
+            };
+
+            // A flagged tx leaves the market but stays censored in the DB, the reports start from it
+            let was_flagged = tracked_tx.status == TxStatus::PotentiallyCensored;
+            tracked_tx.status = new_status.clone();
+            tracked_tx.last_checked = now;
+            sync_fee(&mut fees, tracked_tx);
+            if !was_flagged {
+                changed.push((hash.clone(), new_status));
             }
+        }
+
+        changed
+    }
+
+    // Effective tip percentiles for a block with the given base fee, plus the tip at `threshold_percentile`
+    pub async fn calculate_snapshot(&self, base_fee: U256, threshold_percentile: f64) -> MempoolSnapshot {
+        let tracked = self.tracked_txs.read().await;
+        let mut fees = self.fee_distribution.write().await;
+
+        fees.set_base_fee(base_fee);
+        let percentiles = FeePercentiles {
+            p25: fees.percentile(0.25),
+            p50: fees.percentile(0.50),
+            p75: fees.percentile(0.75),
+            p90: fees.percentile(0.90),
         };
 
         MempoolSnapshot {
             timestamp: current_timestamp(),
+            base_fee,
             fee_percentiles: percentiles,
+            threshold_fee: fees.percentile(threshold_percentile),
             tx_count: tracked.len(),
         }
     }
 
+    // Exact percentile rank of an effective tip among the txs competing right now, priced at `base_fee`
+    pub async fn fee_rank(&self, base_fee: U256, tip: U256) -> f64 {
+        let mut fees = self.fee_distribution.write().await;
+        fees.set_base_fee(base_fee);
+        fees.rank(tip)
+    }
+
     pub async fn get_pending_txs(&self) -> Vec<TrackedTx> {
         let tracked = self.tracked_txs.read().await;
         tracked
@@ -86,23 +263,85 @@ impl MempoolState {
             .collect()
     }
 
-    pub async fn cleanup_old_txs(&self, max_age_secs: i64) {
+    // Currently tracked txs among `hashes`
+    pub async fn get_txs(&self, hashes: &[String]) -> Vec<TrackedTx> {
+        let tracked = self.tracked_txs.read().await;
+        hashes
+            .iter()
+            .filter_map(|hash| tracked.get(hash))
+            .cloned()
+            .collect()
+    }
+
+    // Txs waiting on an earlier nonce, which may become includable later
+    pub async fn get_nonce_gap_txs(&self) -> Vec<TrackedTx> {
+        let tracked = self.tracked_txs.read().await;
+        tracked
+            .values()
+            .filter(|tx| matches!(tx.status, TxStatus::NonceGap))
+            .cloned()
+            .collect()
+    }
+
+    // Expire live txs older than `max_age_secs` (nodes evict txs without telling us) and forget
+    // finished ones. Flagged txs only leave through inclusion or reconcile. Returns the txs marked dropped.
+    pub async fn cleanup_old_txs(&self, max_age_secs: i64) -> Vec<String> {
         let mut tracked = self.tracked_txs.write().await;
         let mut fees = self.fee_distribution.write().await;
 
-        let cutoff = current_timestamp() - max_age_secs;
+        let now = current_timestamp();
+        let cutoff = now - max_age_secs;
+
+        let mut expired = Vec::new();
+        for (hash, tracked_tx) in tracked.iter_mut() {
+            let expires = is_live(&tracked_tx.status) && tracked_tx.status != TxStatus::PotentiallyCensored;
+            if expires && tracked_tx.tx.first_seen <= cutoff {
+                tracked_tx.status = TxStatus::Dropped;
+                tracked_tx.last_checked = now;
+                sync_fee(&mut fees, tracked_tx);
+                expired.push(hash.clone());
+            }
+        }
+
+        tracked.retain(|_, tx| is_live(&tx.status) || tx.last_checked > cutoff);
+        self.sender_nonces.write().await.retain(|_, hash| tracked.contains_key(hash));
+
+        // Only finished txs are pruned, and those already left the fee distribution
+        self.visibility_gaps.write().await.retain(|gap| gap.ended_at > cutoff);
+
+        expired
+    }
+
+    pub async fn record_gap(&self, gap: VisibilityGap) {
+        let mut gaps = self.visibility_gaps.write().await;
+        gaps.push(gap);
+    }
+
+    // Seconds within [from, to] during which none of the `node_count` nodes could see the mempool
+    pub async fn mempool_blind_secs(&self, from: i64, to: i64, node_count: usize) -> i64 {
+        let gaps = self.visibility_gaps.read().await;
 
-        tracked.retain(|_, tx| {
-            matches!(tx.status, TxStatus::Pending | TxStatus::PotentiallyCensored)
-                || tx.last_checked > cutoff
-        });
+        // Sweep over gap boundaries, counting how many nodes are down at each point
+        let mut edges: Vec<(i64, i32)> = gaps.iter()
+            .filter(|gap| gap.source == "mempool")
+            .map(|gap| (gap.started_at.max(from), gap.ended_at.min(to)))
+            .filter(|(start, end)| start < end)
+            .flat_map(|(start, end)| [(start, 1), (end, -1)])
+            .collect();
+        edges.sort();
 
-        fees.clear();
-        for tx in tracked.values() {
-            if matches!(tx.status, TxStatus::Pending) {
-                fees.push(tx.tx.max_priority_fee);
+        let mut blind = 0;
+        let mut down = 0;
+        let mut last = from;
+        for (at, delta) in edges {
+            if down as usize >= node_count {
+                blind += at - last;
             }
+            down += delta;
+            last = at;
         }
+
+        blind
     }
 
     pub async fn get_tx_count(&self) -> usize {
@@ -111,9 +350,123 @@ impl MempoolState {
     }
 }
 
-fn current_timestamp() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64
+// Still competing for inclusion, so it can be replaced
+fn is_live(status: &TxStatus) -> bool {
+    matches!(status, TxStatus::Pending | TxStatus::Queued | TxStatus::NonceGap | TxStatus::PotentiallyCensored)
+}
+
+// Executable and waiting for a builder, so its tip is part of the market
+fn competes(status: &TxStatus) -> bool {
+    matches!(status, TxStatus::Pending | TxStatus::PotentiallyCensored)
+}
+
+// Keep the fee distribution in line with a tx's status
+fn sync_fee(fees: &mut FeeDistribution, tracked_tx: &TrackedTx) {
+    if competes(&tracked_tx.status) {
+        fees.insert(&tracked_tx.tx);
+    } else {
+        fees.remove(&tracked_tx.tx.hash);
+    }
+}
+
+// Whether `new` pays more than `old`, comparing fee cap first so legacy txs order by gas price
+fn outbids(new: &PendingTx, old: &PendingTx) -> bool {
+    (new.max_fee, new.max_priority_fee) > (old.max_fee, old.max_priority_fee)
+}
+
+impl Default for MempoolState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: Address = Address::repeat_byte(0xaa);
+
+    fn tx(hash: &str, max_fee: u64, tip: u64) -> PendingTx {
+        PendingTx {
+            hash: hash.to_string(),
+            from: SENDER,
+            to: None,
+            max_priority_fee: U256::from(tip),
+            max_fee: U256::from(max_fee),
+            nonce: 7,
+            first_seen: current_timestamp(),
+            gas_limit: 21_000,
+            value: U256::ZERO,
+            input_data_size: 0,
+            tx_type: 2,
+            max_fee_per_blob_gas: U256::ZERO,
+            blob_count: 0,
+            delegations: Vec::new(),
+            touched: Vec::new(),
+            sanctions: Vec::new(),
+            queued: false,
+            seen_by: "0:node".to_string(),
+        }
+    }
+
+    async fn status(state: &MempoolState, hash: &str) -> TxStatus {
+        state.get_txs(&[hash.to_string()]).await.remove(0).status
+    }
+
+    #[tokio::test]
+    async fn better_paying_tx_replaces_the_live_one() {
+        let state = MempoolState::new();
+        state.add_tx(tx("0xlow", 20, 1)).await;
+
+        let outcome = state.add_tx(tx("0xhigh", 30, 3)).await;
+        assert_eq!(outcome, AddOutcome::Added { replaced: Some("0xlow".to_string()), replaced_by: None });
+        assert_eq!(status(&state, "0xlow").await, TxStatus::Replaced { by: "0xhigh".to_string() });
+        assert_eq!(status(&state, "0xhigh").await, TxStatus::Pending);
+
+        // Only the replacement is left competing
+        assert_eq!(state.fee_rank(U256::ZERO, U256::from(3)).await, 0.5);
+    }
+
+    #[tokio::test]
+    async fn lower_paying_tx_after_its_replacement_is_replaced_on_arrival() {
+        let state = MempoolState::new();
+        state.add_tx(tx("0xhigh", 30, 3)).await;
+
+        let outcome = state.add_tx(tx("0xlow", 20, 1)).await;
+        assert_eq!(outcome, AddOutcome::Added { replaced: None, replaced_by: Some("0xhigh".to_string()) });
+        assert_eq!(status(&state, "0xlow").await, TxStatus::Replaced { by: "0xhigh".to_string() });
+        assert_eq!(status(&state, "0xhigh").await, TxStatus::Pending);
+        assert_eq!(state.fee_rank(U256::ZERO, U256::from(3)).await, 0.5);
+
+        // Same fees don't outbid either
+        let outcome = state.add_tx(tx("0xsame", 30, 3)).await;
+        assert_eq!(outcome, AddOutcome::Added { replaced: None, replaced_by: Some("0xhigh".to_string()) });
+    }
+
+    #[tokio::test]
+    async fn sightings_of_a_tracked_tx_are_not_replacements() {
+        let state = MempoolState::new();
+        state.add_tx(tx("0xtx", 30, 3)).await;
+
+        assert_eq!(state.add_tx(tx("0xtx", 30, 3)).await, AddOutcome::Duplicate);
+        let mut other_node = tx("0xtx", 30, 3);
+        other_node.seen_by = "1:other".to_string();
+        assert_eq!(state.add_tx(other_node).await, AddOutcome::NewSighting);
+        assert_eq!(status(&state, "0xtx").await, TxStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn replaced_tx_can_still_be_included() {
+        let state = MempoolState::new();
+        state.add_tx(tx("0xlow", 20, 1)).await;
+        state.add_tx(tx("0xhigh", 30, 3)).await;
+
+        // The builder got the original first
+        let block = MinedBlock { number: 10, tx_hashes: vec!["0xlow".to_string()], ..Default::default() };
+        let inclusions = state.mark_included_txs(&block).await;
+
+        assert_eq!(inclusions.len(), 1);
+        assert_eq!(inclusions[0].tx_hash, "0xlow");
+        assert_eq!(status(&state, "0xlow").await, TxStatus::Included { block_number: 10 });
+    }
 }
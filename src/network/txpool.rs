@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use alloy::{
+    primitives::Address,
+    providers::ext::TxPoolApi,
+    rpc::types::{txpool::TxpoolInspectSummary, Transaction},
+};
+use eyre::Result;
+use tokio::sync::mpsc;
+use crate::config::Config;
+use crate::network::mempool::to_pending_tx;
+use crate::network::reconnect::{connect_source, Backoff};
+use crate::network::source::NodeProvider;
+use crate::types::{current_timestamp, PendingTx, TxPoolView};
+
+// Seeds state from the node's txpool on startup, then periodically reconciles it so
+// transactions that were already in the pool (or that we missed) are tracked too.
+pub async fn spawn_poller(
+    config: Config,
+    tx_sender: mpsc::Sender<PendingTx>,
+    pool_sender: mpsc::Sender<TxPoolView>,
+) -> Result<()> {
+    let node = config.primary_node();
+    println!("🔌 Connecting to txpool source at: {}", node.url);
+
+    let mut backoff = Backoff::new();
+    let provider = connect_source(node, &config, "Txpool Poller", &mut backoff).await.provider().clone();
+
+    // Hashes we have already forwarded, so a replacement at a known (sender, nonce) still goes through
+    let mut known: HashMap<String, (Address, u64)> = HashMap::new();
+    // Last inspect round, reconcile rounds only fetch bodies when an entry is new or changed
+    let mut summaries: HashMap<(Address, u64), TxpoolInspectSummary> = HashMap::new();
+
+    let view = seed_from_content(&provider, &node.label, &tx_sender, &mut known).await?;
+    println!("🌱 Seeded {} pending / {} queued txs from txpool", view.pending.len(), view.queued.len());
+    if pool_sender.send(view).await.is_err() {
+        return Ok(());
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.txpool_poll_secs.max(1)));
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        // Taken before the call, txs first seen while it is in flight aren't older than the view
+        let taken_at = current_timestamp();
+
+        // txpool_inspect is much lighter than txpool_content, but isn't served by every client
+        let view = match provider.txpool_inspect().await {
+            Ok(inspect) => {
+                let view = to_view(&node.label, taken_at, &inspect.pending, &inspect.queued);
+                // Summaries carry the gas price, so a fee bump at a known key counts as a change
+                let current: HashMap<(Address, u64), TxpoolInspectSummary> = entries(&inspect.pending)
+                    .chain(entries(&inspect.queued))
+                    .map(|(key, summary)| (key, *summary))
+                    .collect();
+                let has_new = current.iter().any(|(key, summary)| summaries.get(key) != Some(summary));
+                summaries = current;
+                if has_new {
+                    seed_from_content(&provider, &node.label, &tx_sender, &mut known).await?
+                } else {
+                    view
+                }
+            }
+            Err(_) => seed_from_content(&provider, &node.label, &tx_sender, &mut known).await?,
+        };
+
+        // Forget txs that left the pool so the map doesn't grow forever
+        known.retain(|_, key| view.pending.contains(key) || view.queued.contains(key));
+
+        if pool_sender.send(view).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// Fetch the full txpool, forward bodies we haven't seen and return the resulting view
+async fn seed_from_content(
+    provider: &NodeProvider,
+    node_label: &str,
+    tx_sender: &mpsc::Sender<PendingTx>,
+    known: &mut HashMap<String, (Address, u64)>,
+) -> Result<TxPoolView> {
+    let taken_at = current_timestamp();
+    let content = provider.txpool_content().await?;
+
+    let pending = content.pending.values().flat_map(|by_nonce| by_nonce.values()).map(|tx| (tx, false));
+    let queued = content.queued.values().flat_map(|by_nonce| by_nonce.values()).map(|tx| (tx, true));
+    let mut in_pool = HashSet::new();
+    for (tx, is_queued) in pending.chain(queued) {
+        let mut pending_tx = to_pending_tx(tx, taken_at, node_label);
+        pending_tx.queued = is_queued;
+        in_pool.insert(pending_tx.hash.clone());
+
+        let is_new = known.insert(pending_tx.hash.clone(), (pending_tx.from, pending_tx.nonce)).is_none();
+        if is_new && tx_sender.send(pending_tx).await.is_err() {
+            break;
+        }
+    }
+    // Replaced txs keep their key in the pool, forget them by hash
+    known.retain(|hash, _| in_pool.contains(hash));
+
+    Ok(to_view::<Transaction>(node_label, taken_at, &content.pending, &content.queued))
+}
+
+// Keys of both pools as seen at `taken_at`
+fn to_view<T>(
+    node: &str,
+    taken_at: i64,
+    pending: &BTreeMap<Address, BTreeMap<String, T>>,
+    queued: &BTreeMap<Address, BTreeMap<String, T>>,
+) -> TxPoolView {
+    TxPoolView {
+        node: node.to_string(),
+        taken_at,
+        pending: entries(pending).map(|(key, _)| key).collect(),
+        queued: entries(queued).map(|(key, _)| key).collect(),
+    }
+}
+
+// Both txpool methods key entries by sender, then by nonce as a decimal string
+fn entries<T>(pool: &BTreeMap<Address, BTreeMap<String, T>>) -> impl Iterator<Item = ((Address, u64), &T)> {
+    pool.iter().flat_map(|(sender, by_nonce)| {
+        by_nonce.iter().filter_map(move |(nonce, entry)| nonce.parse().ok().map(|n| ((*sender, n), entry)))
+    })
+}
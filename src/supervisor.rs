@@ -0,0 +1,108 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use eyre::Result;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A task that ran this long before failing is considered healthy again
+const HEALTHY_RUNTIME: Duration = Duration::from_secs(60);
+
+// Restarts long-running pipeline tasks with backoff until shutdown is requested
+#[derive(Clone)]
+pub struct Supervisor {
+    shutdown: watch::Receiver<bool>,
+}
+
+impl Supervisor {
+    pub fn new(shutdown: watch::Receiver<bool>) -> Self {
+        Self { shutdown }
+    }
+
+    // Spawn a producer task. It is aborted as soon as shutdown is requested.
+    pub fn spawn<F, Fut>(&self, name: impl Into<String>, task: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        tokio::spawn(run(name.into(), self.shutdown.clone(), true, task))
+    }
+
+    // Spawn a consumer task. It keeps running through shutdown so it can drain
+    // its channel, and is only restarted if it fails before shutdown.
+    pub fn spawn_draining<F, Fut>(&self, name: impl Into<String>, task: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        tokio::spawn(run(name.into(), self.shutdown.clone(), false, task))
+    }
+}
+
+async fn run<F, Fut>(
+    name: String,
+    mut shutdown: watch::Receiver<bool>,
+    abort_on_shutdown: bool,
+    mut task: F,
+) where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        let mut handle = tokio::spawn(task());
+
+        let outcome = if abort_on_shutdown {
+            tokio::select! {
+                res = &mut handle => Some(res),
+                _ = wait_for(&mut shutdown) => {
+                    handle.abort();
+                    None
+                }
+            }
+        } else {
+            Some((&mut handle).await)
+        };
+
+        match outcome {
+            None => {
+                println!("🛑 {} stopped", name);
+                return;
+            }
+            // Consumer finished because its channel closed
+            Some(Ok(Ok(()))) if !abort_on_shutdown => return,
+            _ if abort_on_shutdown && *shutdown.borrow() => {
+                println!("🛑 {} stopped", name);
+                return;
+            }
+            Some(Ok(Ok(()))) => eprintln!("⚠️  {} exited unexpectedly", name),
+            Some(Ok(Err(e))) => eprintln!("❌ {} failed: {:?}", name, e),
+            Some(Err(e)) => eprintln!("❌ {} panicked: {:?}", name, e),
+        }
+
+        if started.elapsed() >= HEALTHY_RUNTIME {
+            backoff = INITIAL_BACKOFF;
+        }
+
+        println!("🔁 Restarting {} in {:?}", name, backoff);
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = wait_for(&mut shutdown) => {
+                // Producers stop here; consumers restart right away to finish draining
+                if abort_on_shutdown {
+                    println!("🛑 {} stopped", name);
+                    return;
+                }
+            }
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+// Resolves once shutdown has been requested (or the sender is gone)
+async fn wait_for(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
@@ -1,14 +1,39 @@
-use alloy::primitives::{U256, Address};
+use std::collections::{HashMap, HashSet};
+use alloy::primitives::{U256, Address, B256, Bytes};
 
 // Status of a tracked t transaction
 #[derive(Debug, Clone, PartialEq)]
 pub enum TxStatus {
     Pending,
+    // In the node's queued pool (not yet executable)
+    Queued,
     Included { block_number: u64 },
     Dropped,
+    // Superseded by another tx with the same (sender, nonce)
+    Replaced { by: String },
+    // Nonce is ahead of the sender's on-chain nonce, can't be included yet
+    NonceGap,
+    // Nonce was already used on-chain, can never be included
+    NonceStale,
     PotentiallyCensored,
 }
 
+impl TxStatus {
+    // Value stored in the transactions.status column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxStatus::Pending => "pending",
+            TxStatus::Queued => "queued",
+            TxStatus::Included { .. } => "included",
+            TxStatus::Dropped => "dropped",
+            TxStatus::Replaced { .. } => "replaced",
+            TxStatus::NonceGap => "nonce_gap",
+            TxStatus::NonceStale => "nonce_stale",
+            TxStatus::PotentiallyCensored => "censored",
+        }
+    }
+}
+
 // Pending transaction data
 #[derive(Debug, Clone)]
 pub struct PendingTx {
@@ -22,6 +47,63 @@ pub struct PendingTx {
     pub gas_limit: u64,
     pub value: U256,
     pub input_data_size: usize,
+    // EIP-2718 type: 0 legacy, 1 access list, 2 dynamic fee, 3 blob, 4 set code
+    pub tx_type: u8,
+    // Blob fee cap and number of blobs, zero for non-blob txs
+    pub max_fee_per_blob_gas: U256,
+    pub blob_count: usize,
+    // EIP-7702 addresses the authorizations delegate to, empty for other types
+    pub delegations: Vec<Address>,
+    // Contracts named in its access list
+    pub touched: Vec<Address>,
+    // Sanctions lists its addresses appear on, empty if none
+    pub sanctions: Vec<String>,
+    // Reported from the node's queued pool: not executable yet, so it starts out as Queued
+    pub queued: bool,
+    // Label of the node that reported it
+    pub seen_by: String,
+}
+
+impl PendingTx {
+    // Legacy (0) and access-list (1) txs only carry a gas price
+    pub fn has_gas_price_only(&self) -> bool {
+        self.tx_type < 2
+    }
+
+    // What the block proposer actually earns per gas at `base_fee`
+    pub fn effective_tip(&self, base_fee: U256) -> U256 {
+        effective_tip(self.max_fee, self.max_priority_fee, base_fee)
+    }
+}
+
+// min(max_priority_fee, max_fee - base_fee), zero if the fee cap doesn't cover the base fee
+pub fn effective_tip(max_fee: U256, max_priority_fee: U256, base_fee: U256) -> U256 {
+    max_priority_fee.min(max_fee.saturating_sub(base_fee))
+}
+
+// Why a tx was not considered for censorship analysis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExclusionReason {
+    NonceGap,
+    NonceStale,
+    // Sender can't cover gas_limit * max_fee, blob gas and value
+    InsufficientBalance,
+    // max_fee is below the current base fee
+    FeeCapBelowBaseFee,
+    // Fails EVM validation when simulated on the latest state
+    NotIncludable,
+}
+
+impl ExclusionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExclusionReason::NonceGap => "nonce_gap",
+            ExclusionReason::NonceStale => "nonce_stale",
+            ExclusionReason::InsufficientBalance => "insufficient_balance",
+            ExclusionReason::FeeCapBelowBaseFee => "fee_cap_below_base_fee",
+            ExclusionReason::NotIncludable => "not_includable",
+        }
+    }
 }
 
 // Transaction wrapper with tracking metadata
@@ -30,6 +112,12 @@ pub struct TrackedTx {
     pub tx: PendingTx,
     pub status: TxStatus,
     pub last_checked: i64,
+    // First time each node reported this tx
+    pub sightings: HashMap<String, i64>,
+    // Set while the tx fails a validity check
+    pub exclusion: Option<ExclusionReason>,
+    // Chain head when we first saw it, 0 if no block had been processed yet
+    pub first_seen_block: u64,
 }
 
 // Fee percentiles for mempool analysis
@@ -45,19 +133,93 @@ pub struct FeePercentiles {
 #[derive(Debug, Clone)]
 pub struct MempoolSnapshot {
     pub timestamp: i64,
+    // Base fee the effective tips were computed against
+    pub base_fee: U256,
+    // Percentiles of effective tips
     pub fee_percentiles: FeePercentiles,
+    // Effective tip at the configured threshold percentile
+    pub threshold_fee: U256,
     pub tx_count: usize,
 }
 
 // Block data for correlation with pending transactions
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct MinedBlock {
     pub number: u64,
+    pub hash: B256,
+    pub parent_hash: B256,
     pub timestamp: u64,
     pub base_fee: U256,
     pub tx_hashes: Vec<String>,
     pub gas_used: u128,
     pub gas_limit: u128,
+    // EIP-4844 blob gas accounting from the header
+    pub blob_gas_used: u64,
+    pub excess_blob_gas: u64,
+    // Blob base fee paid in this block
+    pub blob_base_fee: U256,
+    // Sorted effective tips of the included txs, only fetched for the inclusion threshold
+    pub included_tips: Vec<U256>,
+    // Who built it: the fee recipient, the builder's extra_data tag and, for MEV-Boost
+    // blocks, the relay that delivered it
+    pub fee_recipient: Address,
+    pub extra_data: Bytes,
+    pub relay: Option<String>,
+    pub builder_pubkey: Option<String>,
+    // Beacon slot and proposing validator, when a beacon node is configured
+    pub slot: Option<u64>,
+    pub proposer_index: Option<u64>,
+}
+
+impl MinedBlock {
+    // Printable part of extra_data, where builders put their name
+    pub fn builder_tag(&self) -> Option<String> {
+        let tag: String = self.extra_data.iter()
+            .map(|&b| b as char)
+            .filter(|c| c.is_ascii_graphic() || *c == ' ')
+            .collect();
+        let tag = tag.trim();
+        (!tag.is_empty()).then(|| tag.to_string())
+    }
+
+    // Name to attribute the block to: the builder tag, or the fee recipient for untagged blocks
+    pub fn builder(&self) -> String {
+        self.builder_tag().unwrap_or_else(|| self.fee_recipient.to_string())
+    }
+
+    // Base fee of the next block per EIP-1559
+    pub fn next_base_fee(&self) -> U256 {
+        let target = self.gas_limit / 2;
+        if target == 0 || self.gas_used == target {
+            return self.base_fee;
+        }
+
+        let base_fee = self.base_fee;
+        if self.gas_used > target {
+            let delta = base_fee * U256::from(self.gas_used - target) / U256::from(target) / U256::from(8);
+            base_fee + delta.max(U256::from(1))
+        } else {
+            let delta = base_fee * U256::from(target - self.gas_used) / U256::from(target) / U256::from(8);
+            base_fee.saturating_sub(delta)
+        }
+    }
+}
+
+// What the block monitor reports, in chain order
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    Block(Box<MinedBlock>),
+    // Sent before the blocks of the new chain
+    Reorg(Reorg),
+}
+
+// The chain we followed was replaced above `common_ancestor`
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    pub common_ancestor: u64,
+    // Blocks of the abandoned chain, oldest first
+    pub orphaned: Vec<MinedBlock>,
+    pub detected_at: i64,
 }
 
 // Detected censorship event
@@ -72,6 +234,115 @@ pub struct CensorshipEvent {
     pub blocks_pending: u64,
     pub seconds_pending: i64,
     pub confidence_score: f64,
+    pub seen_by_nodes: usize,
     pub detected_at_block: u64,
     pub detected_at: i64,
+    // Outcome of executing the tx on top of the detection block, if it was simulated
+    pub simulation: Option<SimulationResult>,
+    // Builders of the blocks that had room for the tx but left it out, one entry per builder
+    pub skipped_by: Vec<String>,
+    // Validator indices that proposed those blocks, where known
+    pub skipped_by_proposers: Vec<u64>,
+    // Sanctions lists matching contracts the simulation reached. Only candidates are simulated,
+    // so these stay out of the sanctioned / non-sanctioned comparison.
+    pub sim_sanctions: Vec<String>,
+}
+
+// Result of executing a pending tx in a local EVM
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    pub gas_used: u64,
+    // Passes validation against the latest state; it may still revert
+    pub includable: bool,
+    pub reverted: bool,
+    // Decoded revert reason, halt reason or validation error
+    pub revert_reason: Option<String>,
+    // Accounts the execution touched, empty if it didn't run
+    pub touched: Vec<Address>,
+}
+
+// A tracked tx landing on-chain, with how long it waited
+#[derive(Debug, Clone)]
+pub struct Inclusion {
+    pub tx_hash: String,
+    pub block_number: u64,
+    pub included_at: i64,
+    // None when we saw the tx before the first processed block
+    pub latency_blocks: Option<u64>,
+    pub latency_secs: i64,
+}
+
+// How many txs of one class we tracked and how many of them were flagged
+#[derive(Debug, Clone)]
+pub struct CensorshipRate {
+    pub tx_class: String,
+    pub total: u64,
+    pub censored: u64,
+}
+
+// How often a builder left out flagged txs it had room for
+#[derive(Debug, Clone)]
+pub struct BuilderScore {
+    pub fee_recipient: String,
+    // Tag of its latest block, or the fee recipient if untagged
+    pub builder: String,
+    pub blocks: u64,
+    // Blocks built while at least one flagged tx was eligible
+    pub eligible_blocks: u64,
+    // Eligible blocks that left at least one of them out
+    pub skipped_blocks: u64,
+}
+
+impl BuilderScore {
+    pub fn rate(&self) -> f64 {
+        if self.eligible_blocks == 0 {
+            return 0.0;
+        }
+        self.skipped_blocks as f64 / self.eligible_blocks as f64
+    }
+
+    // 95% Wilson score interval of the rate, stays meaningful for builders with few blocks
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        if self.eligible_blocks == 0 {
+            return (0.0, 1.0);
+        }
+
+        const Z: f64 = 1.96;
+        let n = self.eligible_blocks as f64;
+        let p = self.rate();
+        let denominator = 1.0 + Z * Z / n;
+        let center = (p + Z * Z / (2.0 * n)) / denominator;
+        let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+}
+
+// Window during which a monitor had no connection to the node
+#[derive(Debug, Clone)]
+pub struct VisibilityGap {
+    pub source: String,
+    pub node: String,
+    pub started_at: i64,
+    pub ended_at: i64,
+}
+
+// (sender, nonce) pairs in the node's txpool at a point in time
+#[derive(Debug, Clone, Default)]
+pub struct TxPoolView {
+    // Label of the node the view was taken from
+    pub node: String,
+    pub taken_at: i64,
+    pub pending: HashSet<(Address, u64)>,
+    pub queued: HashSet<(Address, u64)>,
+}
+
+// Mainnet slot length, one block per slot
+pub const SLOT_TIME_SECS: u64 = 12;
+
+// Unix time in seconds
+pub fn current_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
 }